@@ -0,0 +1,71 @@
+/// Whether a key is a dead key, an accent typed before the letter it goes
+/// on. Terminals that get every key as an escape code send these as they
+/// are instead of composing them with the next key. The accents on plain
+/// ASCII keys, like ^ and ~, are only dead keys in that raw mode, since
+/// keyboards that have them as keys of their own type them as they are.
+pub fn is_dead_key(key: char, raw_keys: bool) -> bool {
+    (raw_keys || !key.is_ascii()) && combining_mark(key).is_some()
+}
+
+/// The letter made of an accent typed with a dead key and the letter typed
+/// after it, like ´ and e for é.
+pub fn compose(dead_key: char, letter: char) -> Option<char> {
+    // Letters that take the accent, and the letters they make in the same
+    // order
+    let (letters, composed) = match combining_mark(dead_key)? {
+        '\u{0301}' => ("aeiouyAEIOUYcnszCNSZ", "áéíóúýÁÉÍÓÚÝćńśźĆŃŚŹ"),
+        '\u{0300}' => ("aeiouAEIOU", "àèìòùÀÈÌÒÙ"),
+        '\u{0302}' => ("aeiouAEIOU", "âêîôûÂÊÎÔÛ"),
+        '\u{0308}' => ("aeiouyAEIOUY", "äëïöüÿÄËÏÖÜŸ"),
+        '\u{0303}' => ("anoANO", "ãñõÃÑÕ"),
+        '\u{030A}' => ("auAU", "åůÅŮ"),
+        '\u{0327}' => ("cstCST", "çşţÇŞŢ"),
+        '\u{030C}' => ("cdenrstzCDENRSTZ", "čďěňřšťžČĎĚŇŘŠŤŽ"),
+        _ => return None,
+    };
+    let index = letters.chars().position(|c| c == letter)?;
+    composed.chars().nth(index)
+}
+
+// The combining mark of the accents dead keys send, either as a spacing
+// accent or as the combining mark itself
+fn combining_mark(key: char) -> Option<char> {
+    let mark = match key {
+        '´' | '\u{0301}' => '\u{0301}',
+        '`' | '\u{0300}' => '\u{0300}',
+        '^' | '\u{0302}' => '\u{0302}',
+        '¨' | '\u{0308}' => '\u{0308}',
+        '~' | '\u{0303}' => '\u{0303}',
+        '˚' | '°' | '\u{030A}' => '\u{030A}',
+        '¸' | '\u{0327}' => '\u{0327}',
+        'ˇ' | '\u{030C}' => '\u{030C}',
+        _ => return None,
+    };
+    Some(mark)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_accents_are_only_dead_in_raw_mode() {
+        for key in ['^', '~', '`'] {
+            assert!(!is_dead_key(key, false));
+            assert!(is_dead_key(key, true));
+        }
+        for key in ['´', '¨', 'ˇ', '¸', '\u{0301}'] {
+            assert!(is_dead_key(key, false));
+        }
+        assert!(!is_dead_key('e', true));
+    }
+
+    #[test]
+    fn composes_accented_letters() {
+        assert_eq!(compose('´', 'e'), Some('é'));
+        assert_eq!(compose('^', 'o'), Some('ô'));
+        assert_eq!(compose('¨', 'A'), Some('Ä'));
+        assert_eq!(compose('ˇ', 's'), Some('š'));
+        assert_eq!(compose('´', 'q'), None);
+    }
+}
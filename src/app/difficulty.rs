@@ -0,0 +1,178 @@
+use crate::config::Profile;
+use crate::importer::dictionary::DictionaryWord;
+
+// Words this long or longer count as fully long
+const LONG_WORD: f64 = 15.0;
+// Ranks past this one count as fully rare
+const RARE_RANK: f64 = 50_000.0;
+
+/// How words are picked and how much help is shown while typing them. Each
+/// level is a preset, picked with `--difficulty`, whose settings can be
+/// changed in the config file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difficulty {
+    pub name: String,
+    // How much each trait of a word makes it more likely to be picked,
+    // negative weights make it less likely instead
+    pub length_weight: f64,
+    pub non_ascii_weight: f64,
+    pub rarity_weight: f64,
+    /// Words often mistyped before come up more with a positive weight
+    pub mistakes_weight: f64,
+    pub show_translations: bool,
+    /// Show the last words completed above the current one
+    pub show_history: bool,
+}
+
+impl Difficulty {
+    pub const NAMES: [&'static str; 4] = ["easy", "normal", "hard", "custom"];
+
+    /// The preset of a level. Custom starts out as normal and is meant to be
+    /// changed in the config file.
+    pub fn preset(name: &str) -> Option<Self> {
+        let normal = Self {
+            name: name.to_string(),
+            length_weight: 0.0,
+            non_ascii_weight: 0.0,
+            rarity_weight: 0.0,
+            mistakes_weight: 1.0,
+            show_translations: true,
+            show_history: true,
+        };
+        match name {
+            "easy" => Some(Self {
+                length_weight: -2.0,
+                non_ascii_weight: -2.0,
+                rarity_weight: -3.0,
+                mistakes_weight: -1.0,
+                ..normal
+            }),
+            "normal" | "custom" => Some(normal),
+            "hard" => Some(Self {
+                length_weight: 2.0,
+                non_ascii_weight: 2.0,
+                rarity_weight: 3.0,
+                mistakes_weight: 2.0,
+                show_translations: false,
+                show_history: false,
+                ..normal
+            }),
+            _ => None,
+        }
+    }
+    /// Changes a setting by the name it has in the config file.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let number = || value.parse::<f64>()
+            .map_err(|_| format!("{} takes a number, not \"{}\"", key, value));
+        let flag = || value.parse::<bool>()
+            .map_err(|_| format!("{} takes true or false, not \"{}\"", key, value));
+        match key {
+            "length_weight" => self.length_weight = number()?,
+            "non_ascii_weight" => self.non_ascii_weight = number()?,
+            "rarity_weight" => self.rarity_weight = number()?,
+            "mistakes_weight" => self.mistakes_weight = number()?,
+            "show_translations" => self.show_translations = flag()?,
+            "show_history" => self.show_history = flag()?,
+            _ => return Err(format!("Unknown difficulty setting \"{}\"", key)),
+        }
+        Ok(())
+    }
+    /// How likely a word is to be picked, compared to other words. Every
+    /// trait is scaled from 0 to 1 before being weighed.
+    pub fn weight(&self, word: &DictionaryWord, profile: &Profile) -> f64 {
+        let chars = word.identifier.chars().count().max(1) as f64;
+        let length = (chars / LONG_WORD).min(1.0);
+        let non_ascii = word.identifier.chars().filter(|c| !c.is_ascii()).count() as f64 / chars;
+        // Frequencies fall off fast, so ranks are compared on a log scale.
+        // Words with no rank are taken to be somewhat rare
+        let rarity = match word.rank {
+            Some(rank) => ((rank.max(1) as f64).ln() / RARE_RANK.ln()).min(1.0),
+            None => 0.5,
+        };
+        let mistakes = profile.accuracy.get(&word.identifier)
+            .filter(|accuracy| accuracy.chars_typed > 0)
+            .map(|accuracy| accuracy.chars_failed as f64 / accuracy.chars_typed as f64)
+            .unwrap_or_default();
+        let score = self.length_weight * length
+            + self.non_ascii_weight * non_ascii
+            + self.rarity_weight * rarity
+            + self.mistakes_weight * mistakes;
+        score.exp()
+    }
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Self::preset("normal").unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::config::WordAccuracy;
+    use super::*;
+
+    fn word(identifier: &str, rank: Option<u32>) -> DictionaryWord {
+        let mut word = DictionaryWord::new("noun".into());
+        word.identifier = identifier.into();
+        word.rank = rank;
+        word
+    }
+
+    fn profile() -> Profile {
+        Profile {
+            name: "test".into(),
+            words_learnt: HashMap::new(),
+            accuracy: HashMap::new(),
+            reviews: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn every_level_has_a_preset() {
+        for name in Difficulty::NAMES {
+            assert_eq!(Difficulty::preset(name).unwrap().name, name);
+        }
+        assert!(Difficulty::preset("extreme").is_none());
+        let custom = Difficulty::preset("custom").unwrap();
+        assert_eq!(Difficulty { name: "normal".into(), ..custom }, Difficulty::default());
+        let hard = Difficulty::preset("hard").unwrap();
+        assert!(!hard.show_translations && !hard.show_history);
+        assert!(Difficulty::preset("easy").unwrap().show_translations);
+    }
+
+    #[test]
+    fn easy_picks_short_common_words_and_hard_long_rare_ones() {
+        let profile = profile();
+        let short = word("hus", Some(100));
+        let long = word("sjukhusområdenas", Some(40_000));
+        let easy = Difficulty::preset("easy").unwrap();
+        let hard = Difficulty::preset("hard").unwrap();
+        assert!(easy.weight(&short, &profile) > easy.weight(&long, &profile));
+        assert!(hard.weight(&short, &profile) < hard.weight(&long, &profile));
+        // Normal doesn't care about the word itself
+        let normal = Difficulty::default();
+        assert_eq!(normal.weight(&short, &profile), normal.weight(&long, &profile));
+    }
+
+    #[test]
+    fn words_mistyped_before_come_up_more() {
+        let mut profile = profile();
+        profile.accuracy.insert("hus".into(), WordAccuracy { chars_typed: 10, chars_failed: 5 });
+        let normal = Difficulty::default();
+        assert!(normal.weight(&word("hus", None), &profile) > normal.weight(&word("bil", None), &profile));
+    }
+
+    #[test]
+    fn settings_are_changed_by_name() {
+        let mut difficulty = Difficulty::default();
+        difficulty.set("rarity_weight", "-1.5").unwrap();
+        difficulty.set("show_history", "false").unwrap();
+        assert_eq!(difficulty.rarity_weight, -1.5);
+        assert!(!difficulty.show_history);
+        assert!(difficulty.set("rarity_weight", "lots").is_err());
+        assert!(difficulty.set("show_history", "yes").is_err());
+        assert!(difficulty.set("speed", "1").is_err());
+    }
+}
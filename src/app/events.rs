@@ -1,10 +1,12 @@
 use std::fmt::Display;
 
-use crate::importer::dictionary::Dictionary;
+use crate::importer::{dictionary::Dictionary, error::ImportError};
 
 pub enum AppEvent {
     LoadingStarted,
     DictionaryLoaded(Dictionary),
+    /// The path of the dictionary that failed and why
+    LoadingFailed(String, ImportError),
     LoadingFinished,
 }
 
@@ -14,6 +16,7 @@ impl Display for AppEvent {
             Self::LoadingStarted => "Loading Started",
             Self::LoadingFinished => "Loading Finished",
             Self::DictionaryLoaded(_) => "Dictionary Loaded",
+            Self::LoadingFailed(_, _) => "Loading Failed",
         })
     }
 }
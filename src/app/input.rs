@@ -0,0 +1,79 @@
+/// What happens when a key typed is wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Correction {
+    /// The cursor stays on the char until it is typed right
+    #[default]
+    Stop,
+    /// Wrong chars are typed through and can be corrected with backspace
+    TypeThrough,
+    /// Wrong chars are typed through and stay wrong
+    NoBackspace,
+}
+
+impl Correction {
+    pub const NAMES: [&'static str; 3] = ["stop", "type-through", "no-backspace"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "stop" => Some(Self::Stop),
+            "type-through" => Some(Self::TypeThrough),
+            "no-backspace" => Some(Self::NoBackspace),
+            _ => None,
+        }
+    }
+    pub fn types_through(&self) -> bool {
+        *self != Self::Stop
+    }
+}
+
+/// How a char of the text was typed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Typed {
+    Right,
+    /// Taken, but the letter was typed without its accent
+    NearMiss,
+    Wrong,
+}
+
+#[derive(Debug, Clone)]
+struct TypedChar {
+    typed: Typed,
+    /// Candidates of the word that matched before the char was typed, to
+    /// go back to when it is deleted
+    matching: Vec<usize>,
+}
+
+/// The chars of the text typed so far, one for each key typed. Chars the
+/// punctuation policy skips have none.
+#[derive(Debug, Default)]
+pub struct Input {
+    chars: Vec<TypedChar>,
+}
+
+impl Input {
+    pub fn clear(&mut self) {
+        self.chars.clear();
+    }
+    /// How many keys were typed.
+    pub fn keys(&self) -> usize {
+        self.chars.len()
+    }
+    /// How the char typed with a key was typed, by how many keys came
+    /// before it.
+    pub fn get(&self, key: usize) -> Option<Typed> {
+        self.chars.get(key).map(|typed_char| typed_char.typed)
+    }
+    pub fn push(&mut self, typed: Typed, matching: &[usize]) {
+        self.chars.push(TypedChar { typed, matching: matching.to_vec() });
+    }
+    /// Deletes the last char typed. Gives how it was typed and the
+    /// candidates that matched before it.
+    pub fn pop(&mut self) -> Option<(Typed, Vec<usize>)> {
+        let typed_char = self.chars.pop()?;
+        Some((typed_char.typed, typed_char.matching))
+    }
+    /// Wrong chars that were not corrected.
+    pub fn errors(&self) -> u64 {
+        self.chars.iter().filter(|typed_char| typed_char.typed == Typed::Wrong).count() as u64
+    }
+}
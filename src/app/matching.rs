@@ -0,0 +1,178 @@
+/// How strictly letters with accents have to be typed.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Accents {
+    /// Every letter is typed as it is written
+    #[default]
+    Exact,
+    /// The letter without its accent is taken, as a near-miss
+    BaseLetter,
+    /// Letters can be typed as the sequences of the substitution table
+    Substitute,
+}
+
+impl Accents {
+    pub const NAMES: [&'static str; 3] = ["exact", "base", "substitute"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "exact" => Some(Self::Exact),
+            "base" => Some(Self::BaseLetter),
+            "substitute" => Some(Self::Substitute),
+            _ => None,
+        }
+    }
+}
+
+/// Substitutions used when none are given, for the letters of Swedish,
+/// German and the like.
+pub const DEFAULT_SUBSTITUTIONS: [(&str, char); 6] = [
+    ("aa", 'å'),
+    ("ae", 'ä'),
+    ("oe", 'ö'),
+    ("ue", 'ü'),
+    ("ss", 'ß'),
+    ("o/", 'ø'),
+];
+
+/// What has to be typed for the chars that aren't letters. Chars in none
+/// of the lists are typed as they are.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Punctuation {
+    /// Chars skipped over without typing anything
+    pub skipped: Vec<char>,
+    /// Chars any key that isn't a letter or number types
+    pub separators: Vec<char>,
+    /// Leave out parts of headwords in parentheses, like the "(sig)" of
+    /// "gifta (sig)"
+    pub strip_parentheses: bool,
+}
+
+impl Default for Punctuation {
+    fn default() -> Self {
+        Self {
+            skipped: vec!['(', ')'],
+            separators: vec!['/', '|', '-', '\'', ' '],
+            strip_parentheses: false,
+        }
+    }
+}
+
+impl Punctuation {
+    /// Makes chars typed exactly, whatever list they were in.
+    pub fn make_exact(&mut self, chars: &str) {
+        self.skipped.retain(|c| !chars.contains(*c));
+        self.separators.retain(|c| !chars.contains(*c));
+    }
+}
+
+/// How a key typed matches the char expected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyMatch {
+    Exact,
+    /// Taken, but the letter was typed without its accent
+    NearMiss,
+    /// The start of a substitution, more keys are needed
+    Partial,
+    Miss,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Matching {
+    pub accents: Accents,
+    /// Sequences of keys that can be typed for a letter, like "aa" for å
+    pub substitutions: Vec<(String, char)>,
+    pub punctuation: Punctuation,
+}
+
+impl Matching {
+    /// Reads substitutions written like "aa=å,ss=ß".
+    pub fn parse_substitutions(text: &str) -> Result<Vec<(String, char)>, String> {
+        text.split(',')
+            .filter(|substitution| !substitution.trim().is_empty())
+            .map(|substitution| {
+                let (keys, letter) = substitution.split_once('=')
+                    .ok_or_else(|| format!("Substitutions look like \"aa=å\", not \"{}\"", substitution.trim()))?;
+                let mut letter_chars = letter.trim().chars();
+                match (letter_chars.next(), letter_chars.next()) {
+                    (Some(letter), None) if !keys.trim().is_empty() => Ok((keys.trim().to_string(), letter)),
+                    _ => Err(format!("Substitutions look like \"aa=å\", not \"{}\"", substitution.trim())),
+                }
+            })
+            .collect()
+    }
+    /// Checks a key typed, after the keys of a substitution typed before
+    /// it, against the char expected.
+    pub fn check(&self, expected: char, pending: &str, typed: char) -> KeyMatch {
+        if pending.is_empty() {
+            // Separators are typed by any non letter or number key
+            if expected == typed || (self.punctuation.separators.contains(&expected) && !typed.is_alphanumeric()) {
+                return KeyMatch::Exact;
+            }
+            if self.accents == Accents::BaseLetter && base_letter(expected) == Some(typed) {
+                return KeyMatch::NearMiss;
+            }
+        }
+        if self.accents != Accents::Substitute {
+            return KeyMatch::Miss;
+        }
+        let keys = format!("{}{}", pending, typed);
+        let mut result = KeyMatch::Miss;
+        for (sequence, letter) in &self.substitutions {
+            // Capital letters are typed with the sequence capitalized,
+            // like "Aa" or "AA" for Å
+            let is_letter = *letter == expected || letter.to_uppercase().eq([expected]);
+            if !is_letter || expected.is_uppercase() != keys.starts_with(char::is_uppercase) {
+                continue;
+            }
+            let sequence = sequence.to_lowercase();
+            let keys = keys.to_lowercase();
+            if sequence == keys {
+                return KeyMatch::Exact;
+            }
+            if sequence.starts_with(&keys) {
+                result = KeyMatch::Partial;
+            }
+        }
+        result
+    }
+}
+
+/// The letter without its accent, for the letters of Latin alphabets.
+pub fn base_letter(letter: char) -> Option<char> {
+    let base = match letter {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => 'a',
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' | 'Ā' | 'Ă' | 'Ą' => 'A',
+        'ç' | 'ć' | 'č' | 'ĉ' | 'ċ' => 'c',
+        'Ç' | 'Ć' | 'Č' | 'Ĉ' | 'Ċ' => 'C',
+        'ď' | 'đ' => 'd',
+        'Ď' | 'Đ' => 'D',
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ė' | 'ę' | 'ě' => 'e',
+        'È' | 'É' | 'Ê' | 'Ë' | 'Ē' | 'Ė' | 'Ę' | 'Ě' => 'E',
+        'ğ' | 'ģ' => 'g',
+        'Ğ' | 'Ģ' => 'G',
+        'ì' | 'í' | 'î' | 'ï' | 'ī' | 'į' | 'ı' => 'i',
+        'Ì' | 'Í' | 'Î' | 'Ï' | 'Ī' | 'Į' | 'İ' => 'I',
+        'ķ' => 'k',
+        'Ķ' => 'K',
+        'ł' | 'ľ' | 'ĺ' | 'ļ' => 'l',
+        'Ł' | 'Ľ' | 'Ĺ' | 'Ļ' => 'L',
+        'ñ' | 'ń' | 'ň' | 'ņ' => 'n',
+        'Ñ' | 'Ń' | 'Ň' | 'Ņ' => 'N',
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => 'o',
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ø' | 'Ō' | 'Ő' => 'O',
+        'ř' | 'ŕ' => 'r',
+        'Ř' | 'Ŕ' => 'R',
+        'ś' | 'š' | 'ş' | 'ș' => 's',
+        'Ś' | 'Š' | 'Ş' | 'Ș' => 'S',
+        'ť' | 'ţ' | 'ț' => 't',
+        'Ť' | 'Ţ' | 'Ț' => 'T',
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' | 'ų' => 'u',
+        'Ù' | 'Ú' | 'Û' | 'Ü' | 'Ū' | 'Ů' | 'Ű' | 'Ų' => 'U',
+        'ý' | 'ÿ' => 'y',
+        'Ý' | 'Ÿ' => 'Y',
+        'ź' | 'ż' | 'ž' => 'z',
+        'Ź' | 'Ż' | 'Ž' => 'Z',
+        _ => return None,
+    };
+    Some(base)
+}
@@ -0,0 +1,168 @@
+//! Typing speed and consistency. Speeds count words as 5 chars, the way
+//! typing tests do, so words of any length compare.
+
+const CHARS_PER_WORD: f64 = 5.0;
+// The live speed is taken over the keys typed in this many milliseconds
+const ROLLING_WINDOW: i64 = 10_000;
+const MILLIS_PER_MINUTE: f64 = 60_000.0;
+
+#[derive(Debug, Clone, Copy)]
+struct Keystroke {
+    /// Unix timestamp, in milliseconds
+    at: i64,
+    /// Whether the key was taken, misses are only counted in raw speeds
+    taken: bool,
+}
+
+/// Every key typed in a session, with when it was typed.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    keystrokes: Vec<Keystroke>,
+}
+
+impl Metrics {
+    pub fn record(&mut self, at: i64, taken: bool) {
+        self.keystrokes.push(Keystroke { at, taken });
+    }
+    // Minutes from the first key typed to the last one
+    fn minutes(&self) -> f64 {
+        match (self.keystrokes.first(), self.keystrokes.last()) {
+            (Some(first), Some(last)) => (last.at - first.at) as f64 / MILLIS_PER_MINUTE,
+            _ => 0.0,
+        }
+    }
+    fn taken(&self) -> usize {
+        self.keystrokes.iter().filter(|keystroke| keystroke.taken).count()
+    }
+    /// Chars taken per minute.
+    pub fn cpm(&self) -> f64 {
+        per_minute(self.taken() as f64, self.minutes())
+    }
+    /// Words per minute counting every key typed, misses too.
+    pub fn raw_wpm(&self) -> f64 {
+        per_minute(self.keystrokes.len() as f64 / CHARS_PER_WORD, self.minutes())
+    }
+    /// Words per minute of every key typed, less the errors left in the
+    /// text.
+    pub fn net_wpm(&self, errors_uncorrected: u64) -> f64 {
+        let words = (self.keystrokes.len() as f64 - errors_uncorrected as f64).max(0.0) / CHARS_PER_WORD;
+        per_minute(words, self.minutes())
+    }
+    /// Words per minute of the keys taken in the last seconds before `now`,
+    /// which follows changes in speed as they happen.
+    pub fn rolling_wpm(&self, now: i64) -> f64 {
+        let Some(first) = self.keystrokes.first() else {
+            return 0.0;
+        };
+        let since = now - ROLLING_WINDOW;
+        let taken = self.keystrokes.iter()
+            .rev()
+            .take_while(|keystroke| keystroke.at > since)
+            .filter(|keystroke| keystroke.taken)
+            .count();
+        // Sessions shorter than the window are taken over what they lasted
+        let window = (now - first.at).clamp(0, ROLLING_WINDOW) as f64 / MILLIS_PER_MINUTE;
+        per_minute(taken as f64 / CHARS_PER_WORD, window)
+    }
+    /// Standard deviation of the time between keys, in milliseconds. The
+    /// lower it is, the more even the typing.
+    pub fn consistency(&self) -> f64 {
+        let intervals = self.keystrokes.windows(2)
+            .map(|pair| (pair[1].at - pair[0].at) as f64)
+            .collect::<Vec<f64>>();
+        if intervals.is_empty() {
+            return 0.0;
+        }
+        let mean = intervals.iter().sum::<f64>() / intervals.len() as f64;
+        let variance = intervals.iter()
+            .map(|interval| (interval - mean).powi(2))
+            .sum::<f64>() / intervals.len() as f64;
+        variance.sqrt()
+    }
+}
+
+/// Share of chars typed right, in percent. Nothing typed is nothing
+/// missed.
+pub fn accuracy(chars_typed: u64, chars_failed: u64) -> f64 {
+    if chars_typed == 0 {
+        return 100.0;
+    }
+    100.0 * (1.0 - chars_failed as f64 / chars_typed as f64)
+}
+
+fn per_minute(count: f64, minutes: f64) -> f64 {
+    if minutes <= 0.0 {
+        return 0.0;
+    }
+    count / minutes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A key every second, the ones at the given seconds missed
+    fn session(keys: i64, missed: &[i64]) -> Metrics {
+        let mut metrics = Metrics::default();
+        for second in 0..keys {
+            metrics.record(second * 1000, !missed.contains(&second));
+        }
+        metrics
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn speeds_of_a_session() {
+        // 7 keys over 6 seconds, a tenth of a minute
+        let metrics = session(7, &[3]);
+        assert_close(metrics.raw_wpm(), 14.0);
+        assert_close(metrics.cpm(), 60.0);
+        assert_close(metrics.net_wpm(0), 14.0);
+        assert_close(metrics.net_wpm(2), 10.0);
+        assert_close(metrics.net_wpm(100), 0.0);
+    }
+
+    #[test]
+    fn rolling_speed_only_counts_the_last_seconds() {
+        let metrics = session(21, &[]);
+        // The 10 keys after second 10, over 10 seconds
+        assert_close(metrics.rolling_wpm(20_000), 12.0);
+        // The miss isn't counted
+        let metrics = session(21, &[15]);
+        assert_close(metrics.rolling_wpm(20_000), 10.8);
+        // Shorter sessions are taken over what they lasted
+        let metrics = session(3, &[]);
+        assert_close(metrics.rolling_wpm(3_000), 12.0);
+    }
+
+    #[test]
+    fn consistency_is_the_spread_of_intervals() {
+        assert_close(session(5, &[]).consistency(), 0.0);
+        let mut uneven = Metrics::default();
+        for at in [0, 1000, 4000] {
+            uneven.record(at, true);
+        }
+        assert_close(uneven.consistency(), 1000.0);
+    }
+
+    #[test]
+    fn accuracy_of_chars_typed() {
+        assert_close(accuracy(20, 5), 75.0);
+        assert_close(accuracy(0, 0), 100.0);
+    }
+
+    #[test]
+    fn nothing_typed_has_no_speed() {
+        let metrics = Metrics::default();
+        assert_close(metrics.raw_wpm(), 0.0);
+        assert_close(metrics.net_wpm(0), 0.0);
+        assert_close(metrics.cpm(), 0.0);
+        assert_close(metrics.rolling_wpm(1_000), 0.0);
+        assert_close(metrics.consistency(), 0.0);
+        // A single key has no time to be typed in
+        assert_close(session(1, &[]).raw_wpm(), 0.0);
+    }
+}
@@ -1,18 +1,26 @@
 use std::io::{stdout, Write};
+use std::ops::Range;
+use std::path::Path;
 use std::sync::mpsc;
 use std::thread::{self, sleep};
 use std::time::Duration;
-use crossterm::event::{read, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{read, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use crossterm::style::Color;
 use crossterm::terminal::{Clear, ClearType};
-use rand::distributions::{Uniform, WeightedIndex};
-use rand::prelude::Distribution;
+use rand::distributions::WeightedIndex;
+use rand::prelude::{Distribution, IteratorRandom, SliceRandom};
 use rand::thread_rng;
 use chrono::offset::Utc;
 
+pub mod compose;
 pub mod cursor;
+pub mod difficulty;
 pub mod events;
 pub mod icons;
+pub mod input;
+pub mod matching;
+pub mod metrics;
+pub mod review;
 pub mod word;
 pub mod render;
 pub mod util;
@@ -20,32 +28,51 @@ pub mod util;
 use crate::app::cursor::Cursor;
 use crate::app::events::*;
 use crate::app::render::{Renderer, TextAlign};
+use crate::app::util::wrap_width;
 use crate::app::word::*;
 
-use crate::config::Profile;
+use crate::app::compose::{compose, is_dead_key};
+use crate::app::difficulty::Difficulty;
+use crate::app::input::{Correction, Typed};
+use crate::app::matching::{KeyMatch, Matching};
+use crate::app::metrics::accuracy;
+use crate::app::review::Review;
+use crate::config::{DictionaryPath, Profile};
 use crate::importer;
-use crate::{config::Config, importer::dictionary::Dictionary};
+use crate::importer::error::ImportError;
+use crate::importer::filter::WordFilter;
+use crate::importer::frequency::FrequencyList;
+use crate::importer::parser::ImportOptions;
+use crate::{config::Config, importer::dictionary::{Dictionary, DictionaryPhrase, DictionaryWord, WordPool}};
 
 use self::icons::Icon;
 
-pub const SKIP_CHARACTERS: [char; 2] = [
-    '/', '|'
-];
-
 pub fn create_app(mut config: Config) {
     let stdout = stdout();
 
-    let renderer = Renderer::init();
+    let renderer = Renderer::init(config.raw_keys);
 
     let (ltx, lrx) = mpsc::channel::<AppEvent>();
+    let dictionary_paths = config.dictionary_paths.clone();
+    let import_options = config.import_options.clone();
+    let rebuild_cache = config.rebuild_cache;
+    let frequency_list = config.frequency_list.clone();
+    let word_filter = config.word_filter.clone();
     let _loading_thread = thread::spawn(move || {
         ltx.send(AppEvent::LoadingStarted).unwrap();
-        let dict = importer::parser::parse_file(&config.dictionary_path);
-        ltx.send(AppEvent::DictionaryLoaded(dict)).unwrap();
-        ltx.send(AppEvent::LoadingFinished).unwrap();
+        let loaded = load_dictionaries(&dictionary_paths, &import_options, rebuild_cache)
+            .and_then(|dict| select_words(dict, frequency_list.as_deref(), &word_filter));
+        match loaded {
+            Ok(dict) => {
+                ltx.send(AppEvent::DictionaryLoaded(dict)).unwrap();
+                ltx.send(AppEvent::LoadingFinished).unwrap();
+            }
+            Err((path, err)) => ltx.send(AppEvent::LoadingFailed(path, err)).unwrap(),
+        }
     });
 
     let mut dict: Option<Dictionary> = None;
+    let mut error: Option<(String, ImportError)> = None;
     let mut load_time = 0;
     while let Ok(event) = lrx.recv() {
         match event {
@@ -57,6 +84,9 @@ pub fn create_app(mut config: Config) {
                 dict = Some(loaded_dict);
                 renderer.print_at_center_default("Dictionary Loaded");
             }
+            AppEvent::LoadingFailed(path, err) => {
+                error = Some((path, err));
+            }
             AppEvent::LoadingFinished => {
                 renderer.print_at_center_default( format!(
                     "Finished Loading ({} sec)",
@@ -65,24 +95,57 @@ pub fn create_app(mut config: Config) {
             }
         }
     }
-    let dict = dict.expect("Could not load dictionary");
+    let Some(dict) = dict else {
+        let (path, error) = error.map(|(path, err)| (path, err.to_string()))
+            .unwrap_or(("the dictionary".to_string(), "It was not loaded".to_string()));
+        render_error(&renderer, &path, &error);
+        renderer.exit();
+        eprintln!("Could not load {}: {}", path, error);
+        return;
+    };
     let mut profile = config.profile.clone();
     
     let mut state = State::default();
 
     sleep(Duration::from_millis(500));
-    // Show dictionary loaded
-    renderer.print_at_center(
-        format!("{} -> {}", dict.from, dict.to).as_str(),
-        (0, -6), None, None, None, None,
-    );
+    // Show dictionaries loaded, from the bottom up
+    let sources = dict.sources();
+    for (index, source) in sources.iter().rev().enumerate() {
+        let line = if sources.len() > 1 {
+            format!("{} -> {} ({})", source.from, source.to, source.name)
+        } else {
+            format!("{} -> {}", source.from, source.to)
+        };
+        renderer.print_at_center(
+            line.as_str(),
+            (0, -6 - index as i16), None, None, None, None,
+        );
+    }
+    let difficulty = config.difficulty.clone();
     let mut old_words: Vec<Word> = Vec::new();
-    let mut word = new_word(&dict, &profile);
-    render_translations(&renderer, &word);
+    let reverse = config.reverse;
+    let show_phrases = config.show_phrases;
+    let matching = config.matching.clone();
+    let correction = config.correction;
+    let raw_keys = config.raw_keys;
+    // The word to translate is always shown in reverse mode
+    let show_translations = difficulty.show_translations || reverse;
+    // Words learnt stay learnt, so the pool of new words only shrinks
+    let mut pool = dict.word_pool();
+    let mut word = new_word(&dict, &mut pool, &profile, &difficulty, reverse, show_phrases);
+    start_word(&mut word, &mut state, &matching);
+    if show_translations {
+        render_translations(&renderer, &word);
+    }
     render_center(&renderer, &word, &state, &profile);
     render_cursor(&renderer, &word, &state);
 
     while let Ok(event) = read() {
+        // Terminals that report event types send releases too, only
+        // presses type anything
+        if let Event::Key(KeyEvent { kind: KeyEventKind::Release, .. }) = event {
+            continue;
+        }
         match event {
             Event::Key(KeyEvent {
                 code: KeyCode::Esc, 
@@ -94,63 +157,142 @@ pub fn create_app(mut config: Config) {
             }) => {
                 break;
             }
+            // Ctrl+Backspace, which many terminals send as Ctrl+H, and
+            // Ctrl+W delete back to the start of the word
             Event::Key(KeyEvent {
                 code: KeyCode::Backspace,
+                modifiers: KeyModifiers::CONTROL | KeyModifiers::ALT,
+                ..
+            }) | Event::Key(KeyEvent {
+                code: KeyCode::Char('h' | 'w'),
+                modifiers: KeyModifiers::CONTROL,
                 ..
             }) => {
+                if correction == Correction::NoBackspace {
+                    continue;
+                }
                 state.failed = false;
+                state.dead_key = None;
+                word.pending.clear();
+                if correction.types_through() {
+                    delete_word(&mut word, &mut state);
+                }
+                render_center(&renderer, &word, &state, &profile);
+            }
+            Event::Key(KeyEvent {
+                code: KeyCode::Backspace,
+                ..
+            }) => {
+                if correction == Correction::NoBackspace {
+                    continue;
+                }
+                state.failed = false;
+                // Keys of a dead key or substitution not finished are
+                // deleted before the chars typed
+                if state.dead_key.is_some() || !word.pending.is_empty() {
+                    state.dead_key = None;
+                    word.pending.clear();
+                } else if correction.types_through() {
+                    delete_char(&mut word, &mut state);
+                }
                 render_center(&renderer, &word, &state, &profile);
             }
             Event::Key(KeyEvent {
                 code: KeyCode::Char(c), 
                 ..
             }) => {
-                let chars: Vec<char> = word.original.chars().collect::<Vec<char>>();
-                let current_char: Option<&char> = chars
-                    .get(state.progress);
-                if let Some(current_char) = current_char {
-                    // Check if the character is a skippable one and skip if 
-                    // the user pressed any non letter or number keys
-                    if (SKIP_CHARACTERS.contains(current_char) && !c.is_alphanumeric())
-                    // Progress if the character input was correct
-                    || current_char == &c {
-                        state.progress += 1;
-                        state.failed = false;
-                    } else {
-                        state.failed = true;
-                        state.stats.chars_failed += 1;
+                // Dead keys that the terminal didn't compose wait for the
+                // letter they go on, unless the accent is what is typed
+                let expected = word.original_chars.get(state.progress).copied();
+                let c = match state.dead_key.take() {
+                    Some(dead_key) => match compose(dead_key, c) {
+                        Some(composed) => composed,
+                        // A dead key and a space type the accent itself
+                        None if c == ' ' => dead_key,
+                        None => c,
+                    },
+                    None if is_dead_key(c, raw_keys) && expected != Some(c) => {
+                        state.dead_key = Some(c);
+                        continue;
+                    }
+                    None => c,
+                };
+                if state.progress < word.size {
+                    // Progress if the character input was correct for any
+                    // of the ways the word can be typed
+                    let matching_before = word.matching.clone();
+                    let key_match = word.advance(state.input.keys(), c, &matching);
+                    match key_match {
+                        KeyMatch::Exact => {
+                            type_char(&mut word, &mut state, Typed::Right, &matching_before);
+                            state.failed = false;
+                        }
+                        KeyMatch::NearMiss => {
+                            type_char(&mut word, &mut state, Typed::NearMiss, &matching_before);
+                            state.failed = false;
+                            state.stats.near_misses += 1;
+                            state.word_stats.near_misses += 1;
+                        }
+                        KeyMatch::Partial => {
+                            state.failed = false;
+                        }
+                        KeyMatch::Miss if correction.types_through() => {
+                            type_char(&mut word, &mut state, Typed::Wrong, &matching_before);
+                            state.stats.chars_failed += 1;
+                            state.word_stats.chars_failed += 1;
+                        }
+                        KeyMatch::Miss => miss_char(&mut state),
                     }
                     state.stats.chars_typed += 1;
+                    state.word_stats.chars_typed += 1;
+                    state.metrics.record(Utc::now().timestamp_millis(), key_match != KeyMatch::Miss);
                     stdout.lock().flush().unwrap();
                 }
-                let current_timestamp = Utc::now().timestamp_millis();
-                let diff = current_timestamp - state.last_word_timestamp;
-                state.wpm = 1.0 / (diff as f64 / 1000.0 / 60.0);
+                let diff = Utc::now().timestamp_millis() - state.last_word_timestamp;
                 render_center(&renderer, &word, &state, &profile);
                 if state.progress >= word.size {
-                    // Update last word completed timestamp
-                    state.last_word_timestamp = Utc::now().timestamp_millis();
                     state.stats.completed += 1;
+                    // Wrong chars typed through count once the text is done
+                    let errors = state.input.errors();
+                    state.stats.errors_uncorrected += errors;
+                    state.word_stats.errors_uncorrected += errors;
                     // Update profile
-                    let learnt = profile.words_learnt.get_mut(&word.original);
+                    let learnt = profile.words_learnt.get_mut(&word.identifier);
                     if let Some(learnt) = learnt {
                         *learnt += 1;
                     } else {
-                        profile.words_learnt.insert(word.original.clone(), 1);
+                        profile.words_learnt.insert(word.identifier.clone(), 1);
                     }
+                    let accuracy = profile.accuracy.entry(word.identifier.clone()).or_default();
+                    accuracy.chars_typed += state.word_stats.chars_typed;
+                    accuracy.chars_failed += state.word_stats.chars_failed;
+                    // Schedule the next review of the word from how well
+                    // it was typed this time
+                    let chars_per_second = word.size as f64 / (diff.max(1) as f64 / 1000.0);
+                    let grade = Review::grade(
+                        state.word_stats.chars_typed, state.word_stats.chars_failed, chars_per_second
+                    );
+                    profile.reviews.entry(word.identifier.clone()).or_default()
+                        .update(grade, Utc::now().timestamp());
+                    state.word_stats = Stats::default();
                     // Add last word to the book of words
                     old_words.push(word);
                     if old_words.len() >= 5 {
                         old_words.remove(0);
                     }
-                    render_completed_words(&renderer, &old_words);
-                    // Clear the user input
-                    renderer.clear_line_at_center((0, 2));
+                    if difficulty.show_history {
+                        render_completed_words(&renderer, &old_words);
+                    }
+                    // Clear the last word, which may have taken several
+                    // lines, and everything under it
+                    renderer.clear_down_from_center_at(0);
                     // New word
-                    word = new_word(&dict, &profile);
-                    state.progress = 0;
+                    word = new_word(&dict, &mut pool, &profile, &difficulty, reverse, show_phrases);
+                    start_word(&mut word, &mut state, &matching);
                     render_center(&renderer, &word, &state, &profile);
-                    render_translations(&renderer, &word);
+                    if show_translations {
+                        render_translations(&renderer, &word);
+                    }
                 }
             }
             _ => {},
@@ -158,20 +300,23 @@ pub fn create_app(mut config: Config) {
         render_cursor(&renderer, &word, &state);
     }
     // Show final screen after loop break
-    // Update wpm
-    let current_timestamp = Utc::now().timestamp_millis();
-    let diff = current_timestamp - state.started_at;
-    state.wpm = state.stats.completed as f64 / (diff as f64 / 1000.0 / 60.0);
-    // Render final screen
+    let metrics = &state.metrics;
     let out1 = format!(
-        "Completed: {} words. Average wpm: {}",
-        state.stats.completed, 
-        state.wpm.round(),
+        "Completed: {} words. {} wpm ({} raw), {} cpm, keys ±{} ms apart",
+        state.stats.completed,
+        metrics.net_wpm(state.stats.errors_uncorrected).round(),
+        metrics.raw_wpm().round(),
+        metrics.cpm().round(),
+        metrics.consistency().round(),
     );
     let out2 = format!(
-        "{} chars typed, of which {} were misses ({}% Accuracy).",
-        state.stats.chars_typed, state.stats.chars_failed,
-        100.0 - (state.stats.chars_failed as f64 / state.stats.chars_typed as f64 * 100.0).round(),
+        "{} chars typed, of which {} were misses and {} near-misses ({}% Accuracy).",
+        state.stats.chars_typed, state.stats.chars_failed, state.stats.near_misses,
+        accuracy(state.stats.chars_typed, state.stats.chars_failed).round(),
+    );
+    let out3 = format!(
+        "{} errors corrected and {} left uncorrected.",
+        state.stats.errors_corrected, state.stats.errors_uncorrected,
     );
     renderer.print_at_center(
         out1.as_str(), (0, 2),
@@ -183,6 +328,11 @@ pub fn create_app(mut config: Config) {
         None, Some(Color::DarkYellow), None,
         Some(Clear(ClearType::CurrentLine))
     );
+    renderer.print_at_center(
+        out3.as_str(), (0, 4),
+        None, Some(Color::DarkYellow), None,
+        Some(Clear(ClearType::CurrentLine))
+    );
     // Move cursor out of frame as to continue out of raw mode [rp[[er]]]
     Cursor::move_to_center((0, 8));
     // Save the profile
@@ -190,107 +340,341 @@ pub fn create_app(mut config: Config) {
     config.profile_file.save().expect("Could not save profile data");
 }
 
-pub fn new_word(dict: &Dictionary, profile: &Profile) -> Word {
-    let mut rng = thread_rng();
-    // Select either from the dictionary of from the learnt words
-    let which = WeightedIndex::new([1, 2]).unwrap();
-    if which.sample(&mut rng) == 0 || profile.words_learnt.len() == 0 {
-        // Select a random word from dictionary
-        let distribuition = Uniform::new(0, dict.entries.len());
-        let word_index = distribuition.sample(&mut rng);
-        let word = dict.words.get(word_index);
-        if let Some(word) = word {
-            return Word {
-                size: word.identifier.chars().count(),
-                original: word.identifier.clone(),
-                original_chars: word.identifier.chars().collect(),
-                translation: word.translation.clone(),
-            }
+/// Loads every dictionary given with `--dict` into one, along with the
+/// dictionaries in the directories given. Errors come with the path of the
+/// dictionary that could not be loaded.
+pub fn load_dictionaries(
+    dictionary_paths: &[DictionaryPath],
+    options: &ImportOptions,
+    rebuild_cache: bool,
+) -> Result<Dictionary, (String, ImportError)> {
+    let mut files = Vec::new();
+    for dictionary in dictionary_paths {
+        if Path::new(&dictionary.path).is_dir() {
+            let paths = importer::parser::dictionary_files(&dictionary.path)
+                .map_err(|err| (dictionary.path.clone(), err))?;
+            files.extend(paths.into_iter().map(|path| (path, dictionary.weight)));
+        } else {
+            files.push((dictionary.path.clone(), dictionary.weight));
         }
-    } else {
-        // Select from learnt word
-        let words = profile.words_learnt.clone().into_iter().collect::<Vec<(String, i64)>>();
-        let distribuition = Uniform::new(0, words.len());
-        let word_index = distribuition.sample(&mut rng);
-        if let Some((word, _)) = words.get(word_index) {
-            // Find the word in the dictionary
-            let dict_word = dict.words.iter()
-                .find(|d| d.identifier == word.to_string());
-            if let Some(word) = dict_word {
-                return Word {
-                    size: word.identifier.chars().count(),
-                    original: word.identifier.clone(),
-                    original_chars: word.identifier.chars().collect(),
-                    translation: word.translation.clone(),
-                }
-            }
+    }
+    let mut merged: Option<Dictionary> = None;
+    for (path, weight) in files {
+        let name = Path::new(&path).file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or(path.clone());
+        let dict = importer::cache::parse_file_cached(&path, options, rebuild_cache)
+            .map_err(|err| (path.clone(), err))?
+            .with_source(&name, weight);
+        match merged.as_mut() {
+            Some(merged) => merged.extend(dict),
+            None => merged = Some(dict),
         }
     }
-    panic!("Word could not be selected, out of bounds");
+    let all_paths = || dictionary_paths.iter()
+        .map(|dictionary| dictionary.path.as_str())
+        .collect::<Vec<&str>>()
+        .join(", ");
+    match merged {
+        Some(merged) if merged.words().len() > 0 => Ok(merged),
+        Some(_) => Err((all_paths(), ImportError::malformed("There are no words in the dictionary"))),
+        None => Err((all_paths(), ImportError::malformed("There are no dictionaries in it"))),
+    }
+}
+
+/// Ranks the words of a dictionary with the frequency list, when there is
+/// one, and leaves out the words the filter doesn't keep.
+pub fn select_words(
+    mut dict: Dictionary,
+    frequency_list: Option<&str>,
+    filter: &WordFilter,
+) -> Result<Dictionary, (String, ImportError)> {
+    if let Some(path) = frequency_list {
+        let frequencies = FrequencyList::parse_file(path)
+            .map_err(|err| (path.to_string(), err))?;
+        dict.rank_words(&frequencies);
+    }
+    if !filter.is_empty() {
+        dict.retain_words(|word| filter.keeps(word));
+    }
+    if dict.words().len() == 0 {
+        let names = dict.sources().iter()
+            .map(|source| source.name.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ");
+        return Err((names, ImportError::malformed("None of the words are kept by the word filters")));
+    }
+    Ok(dict)
+}
+
+/// Shows why the dictionary could not be loaded and waits for a key, so
+/// the message is seen before leaving the alternate screen.
+pub fn render_error(renderer: &Renderer, path: &str, error: &str) {
+    renderer.print_at_center(
+        format!("Could not load {}", path).as_str(), (0, -1),
+        Some(TextAlign::Center), Some(Color::Red), None,
+        Some(Clear(ClearType::CurrentLine))
+    );
+    renderer.print_at_center(
+        error, (0, 1),
+        Some(TextAlign::Center), None, None,
+        Some(Clear(ClearType::CurrentLine))
+    );
+    renderer.print_at_center(
+        "Press any key to exit", (0, 3),
+        Some(TextAlign::Center), Some(Color::DarkGrey), None,
+        Some(Clear(ClearType::CurrentLine))
+    );
+    while let Ok(event) = read() {
+        if let Event::Key(KeyEvent { kind: KeyEventKind::Press, .. }) = event {
+            break;
+        }
+    }
+}
+
+/// Gets a new word ready to be typed, leaving out what the punctuation
+/// policy says isn't typed.
+pub fn start_word(word: &mut Word, state: &mut State, matching: &Matching) {
+    if matching.punctuation.strip_parentheses {
+        word.strip_parentheses();
+    }
+    word.skipped = matching.punctuation.skipped.clone();
+    state.progress = word.position(0);
+    // Words are timed from when they are shown, not counting loading
+    state.last_word_timestamp = Utc::now().timestamp_millis();
+    state.missed = false;
+    state.input.clear();
+}
+
+// Moves past the char at the cursor, typed as it was, and the chars skipped
+// after it
+fn type_char(word: &mut Word, state: &mut State, typed: Typed, matching_before: &[usize]) {
+    // However many misses came before, the char is corrected once
+    if state.missed && typed != Typed::Wrong {
+        state.missed = false;
+        state.stats.errors_corrected += 1;
+        state.word_stats.errors_corrected += 1;
+    }
+    state.input.push(typed, matching_before);
+    state.progress = word.position(state.input.keys());
+}
+
+// A wrong key at the cursor when errors are corrected before moving on. The
+// char has to be typed right next, which corrects it
+fn miss_char(state: &mut State) {
+    state.failed = true;
+    state.missed = true;
+    state.stats.chars_failed += 1;
+    state.word_stats.chars_failed += 1;
+}
+
+// Deletes the last char typed, gives whether there was one to delete
+fn delete_char(word: &mut Word, state: &mut State) -> bool {
+    let Some((typed, matching_before)) = state.input.pop() else {
+        return false;
+    };
+    if typed == Typed::Wrong {
+        state.stats.errors_corrected += 1;
+        state.word_stats.errors_corrected += 1;
+    }
+    word.restore(matching_before);
+    state.progress = word.position(state.input.keys());
+    true
+}
+
+// Deletes the chars typed back to the start of the word at the cursor, or of
+// the word before when the cursor is right after a space
+fn delete_word(word: &mut Word, state: &mut State) {
+    delete_char(word, state);
+    while state.progress > 0 && word.original_chars[state.progress - 1] != ' ' {
+        if !delete_char(word, state) {
+            break;
+        }
+    }
+}
+
+// How many words are weighed against each other by the difficulty every
+// time a word is picked
+const WORD_CANDIDATES: usize = 8;
+
+/// Picks the next word to type. Learnt words that are due for review come
+/// first, the most overdue one first, and only when none is due are new
+/// words picked, weighed by the difficulty. With phrases on, new words are
+/// picked through their example phrases while there are any left, and
+/// words due with example phrases are typed through one of them.
+pub fn new_word(dict: &Dictionary, pool: &mut WordPool, profile: &Profile, difficulty: &Difficulty, reverse: bool, phrases: bool) -> Word {
+    let make_word = |word: &DictionaryWord| {
+        if reverse {
+            return Word::reversed(word);
+        }
+        let phrase = dict.phrases_for(&word.identifier)
+            .filter(|phrase| phrases && !phrase.identifier.trim().is_empty())
+            .choose(&mut thread_rng());
+        match phrase {
+            Some(phrase) => Word::from_phrase(word, Phrase::from(phrase)),
+            None => Word::from(word),
+        }
+    };
+    let now = Utc::now().timestamp();
+    // Words learnt before reviews were kept are due right away, and learnt
+    // words that are no longer in the dictionary are skipped
+    let due = profile.words_learnt.keys()
+        .map(|word| (word, profile.reviews.get(word)))
+        .filter(|(_, review)| review.is_none_or(|review| review.is_due(now)))
+        .filter_map(|(word, review)| dict.lookup(word).map(|word| (word, review.map_or(0, |review| review.due))))
+        .min_by_key(|(_, due)| *due);
+    if let Some((word, _)) = due {
+        return make_word(word);
+    }
+    let is_new = |word: &DictionaryWord| !profile.words_learnt.contains_key(&word.identifier);
+    // Phrase mode picks among the phrases themselves, so it shows phrases
+    // however few words have them, until the words of every phrase were
+    // typed
+    if phrases && !reverse {
+        let candidates = (0..WORD_CANDIDATES)
+            .filter_map(|_| pool.pick_phrase(dict, |phrase| {
+                !phrase.identifier.trim().is_empty() && dict.lookup(&phrase.example_for).is_some_and(is_new)
+            }))
+            .filter_map(|phrase| Some((dict.lookup(&phrase.example_for)?, phrase)))
+            .collect::<Vec<(&DictionaryWord, &DictionaryPhrase)>>();
+        if let Some((word, phrase)) = choose_by_weight(&candidates, |(word, _)| difficulty.weight(word, profile)) {
+            return Word::from_phrase(word, Phrase::from(*phrase));
+        }
+    }
+    // Select random words from dictionary that were never typed, or any
+    // word once every one of them was
+    let mut candidates = (0..WORD_CANDIDATES)
+        .filter_map(|_| pool.pick(dict, is_new))
+        .collect::<Vec<&DictionaryWord>>();
+    if candidates.is_empty() {
+        candidates = (0..WORD_CANDIDATES)
+            .filter_map(|_| dict.random_word())
+            .collect();
+    }
+    let word = choose_by_weight(&candidates, |word| difficulty.weight(word, profile))
+        .expect("Word could not be selected, the dictionary has no words");
+    make_word(word)
+}
+
+// Picks a candidate by weight, or any of them when the weights can't be used,
+// like when every one is 0
+fn choose_by_weight<T>(candidates: &[T], weight: impl Fn(&T) -> f64) -> Option<&T> {
+    let mut rng = thread_rng();
+    match WeightedIndex::new(candidates.iter().map(weight)) {
+        Ok(index) => Some(&candidates[index.sample(&mut rng)]),
+        Err(_) => candidates.choose(&mut rng),
+    }
 }
 
 pub fn render_center(renderer: &Renderer, word: &Word, state: &State, profile: &Profile) {
-    renderer.clear_line_at_center((0,0));
-    let half_word = word.size as i16 / 2;
-    // Update progress display
-    let progress_str = format!("{}/{}", state.progress, word.size);
-    let _progress_len = progress_str.len() as i16;
+    let lines = word.lines(wrap_width());
+    for row in 0..lines.len() {
+        renderer.clear_line_at_center((0, 2 * row as i16));
+    }
+    let widest = lines.iter().map(|line| line_width(word, line)).max().unwrap_or_default();
+    let half_word = widest as i16 / 2;
+    // Update progress display, phrases count the words typed
+    let progress_str = if word.is_phrase {
+        let (typed, total) = word.words_typed(state.progress);
+        format!("{}/{} words", typed, total)
+    } else {
+        format!("{}/{}", state.progress, word.size)
+    };
     renderer.print_at_center(
         progress_str.as_str(),
         (half_word + 4, 0), Some(TextAlign::Left), 
         Some(Color::DarkGrey), None,
         None
     );
-    // Update wpm display
+    // Update wpm display, with the speed of the last few seconds
+    let wpm = state.metrics.rolling_wpm(Utc::now().timestamp_millis());
     renderer.print_at_center(
-        format!("{} wpm", state.wpm.round()).as_str(), 
+        format!("{} wpm", wpm.round()).as_str(), 
         (- half_word - 4, 0), Some(TextAlign::Right), 
         Some(Color::DarkYellow), None,
         None
     );
-    // Update word shown
-    let left  = word.original_chars[..state.progress].into_iter().collect::<String>();
-    let right = word.original_chars[state.progress..].into_iter().collect::<String>();
-    let fail_char = word.original_chars.get(state.progress).unwrap_or(&' ');
-    let left_x   = - half_word;
-    let right_x  = left_x + state.progress as i16;
-    renderer.print_at_center(
-        left.as_str(),
-        (left_x, 0), Some(TextAlign::Left),
-        Some(Color::DarkGreen), None, None
-    );
-    renderer.print_at_center(
-        right.as_str(),
-        (right_x, 0), Some(TextAlign::Left),
-        None, None, None
-    );
-    if state.failed {
+    // Update word shown, a line at a time with a row for the cursor
+    // between them
+    for (row, line) in lines.iter().enumerate() {
+        let y = 2 * row as i16;
+        let typed_end = state.progress.clamp(line.start, line.end);
+        let right = word.original_chars[typed_end..line.end].iter().collect::<String>();
+        let left_x   = - (line_width(word, line) as i16 / 2);
+        let right_x  = left_x + (typed_end - line.start) as i16;
+        // Chars typed are green, and the ones typed through wrong are red,
+        // printed in runs of the same color
+        let mut run_start = line.start;
+        while run_start < typed_end {
+            let is_wrong = |position: usize| {
+                word.keys_before(position).and_then(|keys| state.input.get(keys)) == Some(Typed::Wrong)
+            };
+            let wrong = is_wrong(run_start);
+            let run_end = (run_start..typed_end)
+                .find(|position| is_wrong(*position) != wrong)
+                .unwrap_or(typed_end);
+            let run = word.original_chars[run_start..run_end].iter()
+                .map(|c| if wrong && *c == ' ' { '_' } else { *c })
+                .collect::<String>();
+            renderer.print_at_center(
+                run.as_str(),
+                (left_x + (run_start - line.start) as i16, y), Some(TextAlign::Left),
+                Some(if wrong { Color::DarkRed } else { Color::DarkGreen }), None, None
+            );
+            run_start = run_end;
+        }
         renderer.print_at_center(
-            format!("{}", fail_char).as_str(),
-            (right_x, 0), Some(TextAlign::Left),
-            Some(Color::DarkRed), None, None
+            right.as_str(),
+            (right_x, y), Some(TextAlign::Left),
+            None, None, None
         );
+        if state.failed && line.contains(&state.progress) {
+            // Missed spaces are shown, or there would be nothing red
+            let fail_char = match word.original_chars[state.progress] {
+                ' ' => '_',
+                c => c,
+            };
+            renderer.print_at_center(
+                format!("{}", fail_char).as_str(),
+                (right_x, y), Some(TextAlign::Left),
+                Some(Color::DarkRed), None, None
+            );
+        }
+    }
+    let learnt = profile.words_learnt.get(&word.identifier).unwrap_or(&0);
+    render_knowledge(renderer, *learnt, extra_rows(word));
+}
+
+// Width of a line without the space it may end with
+fn line_width(word: &Word, line: &Range<usize>) -> usize {
+    match word.original_chars[line.clone()].last() {
+        Some(' ') => line.len() - 1,
+        _ => line.len(),
     }
-    let learnt = profile.words_learnt.get(&word.original).unwrap_or(&0);
-    render_knowledge(renderer, *learnt);
 }
 
-pub fn render_knowledge(renderer: &Renderer, learnt: i64) {
+/// Rows taken by the lines of a phrase past the first one, which push down
+/// what is shown below it.
+pub fn extra_rows(word: &Word) -> i16 {
+    2 * (word.lines(wrap_width()).len() as i16 - 1)
+}
+
+pub fn render_knowledge(renderer: &Renderer, learnt: i64, offset_y: i16) {
     renderer.print_at_center(
         format!("{} {}", learnt, Icon::from(learnt)).as_str(),
-        (0, 2), Some(TextAlign::Left), 
+        (0, 2 + offset_y), Some(TextAlign::Left), 
         Some(Color::Green), None,
         None
     );
 }
 
-pub fn render_completed_words(renderer: &Renderer, words: &Vec<Word>) {
+pub fn render_completed_words(renderer: &Renderer, words: &[Word]) {
     for i in 0..(words.len()) {
         let word = words.get((words.len() - 1) - i).unwrap();
         renderer.clear_line_at_center((0, -2 - i as i16));
         // Show the completed word in grey
         renderer.print_at_center(
-            format!("{}", word.original).as_str(), 
+            shorten(&word.original).as_str(), 
             (-2, -2 - i as i16),
             Some(TextAlign::Right), Some(Color::DarkGrey), None,
             None,
@@ -302,10 +686,7 @@ pub fn render_completed_words(renderer: &Renderer, words: &Vec<Word>) {
             None,
         );
         renderer.print_at_center(
-            format!(
-                "{}", 
-                word.translation.first().unwrap_or(&"no translation".to_string())
-            ).as_str(), 
+            shorten(word.translation.first().map(String::as_str).unwrap_or("no translation")).as_str(), 
             (2, -2 - i as i16),
             Some(TextAlign::Left), Some(Color::DarkGrey), None,
             None,
@@ -313,16 +694,26 @@ pub fn render_completed_words(renderer: &Renderer, words: &Vec<Word>) {
     }
 }
 
+// Phrases are cut to this many chars in the completed words
+const HISTORY_WIDTH: usize = 30;
+
+fn shorten(text: &str) -> String {
+    if text.chars().count() <= HISTORY_WIDTH {
+        return text.to_string();
+    }
+    let mut short = text.chars().take(HISTORY_WIDTH - 1).collect::<String>();
+    short.push('…');
+    short
+}
+
 pub fn render_translations(renderer: &Renderer, word: &Word) {
-    renderer.clear_down_from_center_at(3);
+    let offset_y = extra_rows(word);
+    renderer.clear_down_from_center_at(3 + offset_y);
     for i in 0..(word.translation.len()) {
         let translation = word.translation.get(i).unwrap();
         // Show the completed word in grey
         renderer.print_at_center(
-            format!(
-                "{}", 
-                translation
-            ).as_str(), (0, 3 + i as i16),
+            translation.as_str(), (0, 3 + offset_y + i as i16),
             None, None, None,
             None,
         );
@@ -331,29 +722,192 @@ pub fn render_translations(renderer: &Renderer, word: &Word) {
 
 pub fn render_new_word(renderer: &Renderer, word: &Word) {
     renderer.print_at_center(
-        format!(
-            "{}", 
-            word.original,
-        ).as_str(), (0,0), None,
+        word.original.as_str(), (0,0), None,
         None, None,
         Some(Clear(ClearType::CurrentLine))
     );
 }
 
 pub fn render_cursor(renderer: &Renderer, word: &Word, state: &State) {
+    let lines = word.lines(wrap_width());
+    let (x, y) = get_progress_cursor(word, state);
+    // Only the row under the line being typed has a cursor
+    for row in 0..lines.len() {
+        renderer.clear_line_at_center((0, 2 * row as i16 + 1));
+    }
     renderer.print_at_center(
-        "^", (get_progress_cursor(word, state), 1), 
+        "^", (x, y + 1), 
         None, 
         Some(Color::DarkYellow), None, 
         Some(Clear(ClearType::CurrentLine))
     );
 }
 
-pub fn get_progress_cursor(word: &Word, state: &State) -> i16 {
-    state.progress as i16 - (word.size / 2) as i16
+/// Where the next char to type is, from the center.
+pub fn get_progress_cursor(word: &Word, state: &State) -> (i16, i16) {
+    let lines = word.lines(wrap_width());
+    let row = lines.iter()
+        .position(|line| line.contains(&state.progress))
+        .unwrap_or(lines.len() - 1);
+    let line = &lines[row];
+    let x = (state.progress - line.start) as i16 - (line_width(word, line) / 2) as i16;
+    (x, 2 * row as i16)
 }
 
 pub fn reset_cursor(word: &Word, state: &State) {
-    let new_cursor_pos_x = get_progress_cursor(word, state) + (if state.failed {1} else {0});
-    Cursor::move_to_center((new_cursor_pos_x, 0));
-}
\ No newline at end of file
+    let (x, y) = get_progress_cursor(word, state);
+    let new_cursor_pos_x = x + (if state.failed {1} else {0});
+    Cursor::move_to_center((new_cursor_pos_x, y));
+}
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::importer::dictionary::DictionaryEntry;
+    use super::*;
+
+    #[test]
+    fn misses_at_a_char_are_corrected_once() {
+        let mut dictionary_word = DictionaryWord::new("noun".into());
+        dictionary_word.identifier = "ord".into();
+        let matching = Matching::default();
+        let mut word = Word::from(&dictionary_word);
+        let mut state = State::default();
+        start_word(&mut word, &mut state, &matching);
+        miss_char(&mut state);
+        miss_char(&mut state);
+        let before = word.matching.clone();
+        type_char(&mut word, &mut state, Typed::Right, &before);
+        let before = word.matching.clone();
+        type_char(&mut word, &mut state, Typed::Right, &before);
+        assert_eq!(state.stats.chars_failed, 2);
+        assert_eq!(state.stats.errors_corrected, 1);
+        assert_eq!(state.word_stats.errors_corrected, 1);
+        assert!(!state.missed);
+    }
+
+    #[test]
+    fn phrase_mode_shows_phrases_of_few_words() {
+        let mut entries = (0..100)
+            .map(|number| {
+                let mut word = DictionaryWord::new("noun".into());
+                word.identifier = format!("ord{}", number);
+                DictionaryEntry::Word(word)
+            })
+            .collect::<Vec<DictionaryEntry>>();
+        let mut phrase = DictionaryPhrase::new("example".into());
+        phrase.identifier = "ett ord42 till".into();
+        phrase.example_for = "ord42".into();
+        entries.push(DictionaryEntry::Phrase(phrase));
+        let dict = Dictionary::new(entries, "Swedish".into(), "English".into());
+        let mut profile = Profile {
+            name: "test".into(),
+            words_learnt: HashMap::new(),
+            accuracy: HashMap::new(),
+            reviews: HashMap::new(),
+        };
+        let difficulty = Difficulty::default();
+        let mut pool = dict.word_pool();
+        for _ in 0..10 {
+            let word = new_word(&dict, &mut pool, &profile, &difficulty, false, true);
+            assert!(word.is_phrase);
+            assert_eq!(word.identifier, "ord42");
+            assert_eq!(word.original, "ett ord42 till");
+        }
+        // Once its word was typed, and until it is due for review, words
+        // without phrases come up instead
+        profile.words_learnt.insert("ord42".into(), 1);
+        let mut review = Review::default();
+        review.update(5, Utc::now().timestamp());
+        profile.reviews.insert("ord42".into(), review);
+        let word = new_word(&dict, &mut pool, &profile, &difficulty, false, true);
+        assert!(!word.is_phrase);
+        assert_ne!(word.identifier, "ord42");
+    }
+
+    // Types keys the way type-through does, leaving out the ones that
+    // start a substitution
+    fn type_through(word: &mut Word, state: &mut State, keys: &str, matching: &Matching) {
+        for key in keys.chars() {
+            let before = word.matching.clone();
+            let typed = match word.advance(state.input.keys(), key, matching) {
+                KeyMatch::Exact => Typed::Right,
+                KeyMatch::NearMiss => Typed::NearMiss,
+                KeyMatch::Partial => continue,
+                KeyMatch::Miss => Typed::Wrong,
+            };
+            type_char(word, state, typed, &before);
+        }
+    }
+
+    fn started(identifier: &str, matching: &Matching) -> (Word, State) {
+        let mut dictionary_word = DictionaryWord::new("noun".into());
+        dictionary_word.identifier = identifier.into();
+        let mut word = Word::from(&dictionary_word);
+        let mut state = State::default();
+        start_word(&mut word, &mut state, matching);
+        (word, state)
+    }
+
+    #[test]
+    fn deleting_a_char_restores_the_translations_that_matched() {
+        let mut dictionary_word = DictionaryWord::new("verb".into());
+        dictionary_word.identifier = "skära".into();
+        dictionary_word.translation = vec!["cut".into(), "slice".into()];
+        let matching = Matching::default();
+        let mut word = Word::reversed(&dictionary_word);
+        let mut state = State::default();
+        start_word(&mut word, &mut state, &matching);
+        type_through(&mut word, &mut state, "sl", &matching);
+        assert_eq!(word.original, "slice");
+        assert_eq!(word.matching, [1]);
+        assert!(delete_char(&mut word, &mut state));
+        assert!(delete_char(&mut word, &mut state));
+        assert!(!delete_char(&mut word, &mut state));
+        assert_eq!(word.matching, [0, 1]);
+        assert_eq!(state.progress, 0);
+        // The other translation can be typed again
+        type_through(&mut word, &mut state, "cut", &matching);
+        assert_eq!(word.original, "cut");
+        assert_eq!(state.progress, word.size);
+        assert_eq!(state.input.errors(), 0);
+    }
+
+    #[test]
+    fn deleting_a_wrong_char_corrects_it() {
+        let matching = Matching::default();
+        let (mut word, mut state) = started("ord", &matching);
+        type_through(&mut word, &mut state, "ox", &matching);
+        assert_eq!(state.progress, 2);
+        assert_eq!(state.input.errors(), 1);
+        delete_char(&mut word, &mut state);
+        assert_eq!(state.progress, 1);
+        assert_eq!(state.input.errors(), 0);
+        assert_eq!(state.stats.errors_corrected, 1);
+        assert_eq!(state.word_stats.errors_corrected, 1);
+        // Deleting a right char corrects nothing
+        delete_char(&mut word, &mut state);
+        assert_eq!(state.stats.errors_corrected, 1);
+        type_through(&mut word, &mut state, "ord", &matching);
+        assert_eq!(state.progress, word.size);
+        assert_eq!(state.input.errors(), 0);
+    }
+
+    #[test]
+    fn words_are_deleted_back_to_their_start() {
+        let matching = Matching::default();
+        let (mut word, mut state) = started("god morgon", &matching);
+        type_through(&mut word, &mut state, "god mxrg", &matching);
+        assert_eq!(state.progress, 8);
+        delete_word(&mut word, &mut state);
+        assert_eq!(state.progress, 4);
+        assert_eq!(state.input.keys(), 4);
+        assert_eq!(state.input.errors(), 0);
+        assert_eq!(state.stats.errors_corrected, 1);
+        // Right after a space, the word before goes with it
+        delete_word(&mut word, &mut state);
+        assert_eq!(state.progress, 0);
+        assert_eq!(state.input.keys(), 0);
+        delete_word(&mut word, &mut state);
+        assert_eq!(state.progress, 0);
+    }
+}
@@ -15,23 +15,27 @@ pub enum TextAlign {
 }
 
 impl Renderer {
-    pub fn init () -> Self {
+    /// Takes over the terminal. Keys come in as text, so the terminal or
+    /// input method composes dead keys and such before they get here,
+    /// unless `raw_keys` asks for every key as an escape code.
+    pub fn init (raw_keys: bool) -> Self {
         enable_raw_mode()
         .expect("This app requires raw mode to be available in order to function correctly");
         let mut stdout = stdout();
+        let mut flags = KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+            | KeyboardEnhancementFlags::REPORT_EVENT_TYPES;
+        if raw_keys {
+            flags |= KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES;
+        }
         execute!(
             stdout,
             EnterAlternateScreen,
             Hide,
-            PushKeyboardEnhancementFlags(
-                KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
-                    | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
-                    | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
-            )
+            PushKeyboardEnhancementFlags(flags)
         ).unwrap();
         Self
     }
-    pub fn exit(self: &Self) {
+    pub fn exit(&self) {
         let mut stdout = stdout();
         execute!(
             stdout,
@@ -42,14 +46,14 @@ impl Renderer {
     
         disable_raw_mode().unwrap();
     }
-    pub fn reset_color(self: &Self) {
+    pub fn reset_color(&self) {
         let mut stdout = stdout();
         execute!(
             stdout,
             ResetColor,
         ).expect("Could not reset terminal colours");
     }
-    pub fn print(self: &Self, word: &str, color: Option<Color>, background_color: Option<Color>) {
+    pub fn print(&self, word: &str, color: Option<Color>, background_color: Option<Color>) {
         let (row, col) = position().unwrap();
         self.print_at(
             word, 
@@ -59,7 +63,7 @@ impl Renderer {
             Some(Clear(ClearType::CurrentLine))
         )
     }
-    pub fn print_at_center_default(self: &Self, word: &str) {
+    pub fn print_at_center_default(&self, word: &str) {
         self.print_at(
             word, term_center(), 
             TextAlign::Center, 
@@ -67,7 +71,7 @@ impl Renderer {
             Some(Clear(ClearType::CurrentLine))
         )
     }
-    pub fn print_at_center(self: &Self, word: &str, offset: (i16, i16), align: Option<TextAlign>, color: Option<Color>, background_color: Option<Color>, clear: Option<Clear>) {
+    pub fn print_at_center(&self, word: &str, offset: (i16, i16), align: Option<TextAlign>, color: Option<Color>, background_color: Option<Color>, clear: Option<Clear>) {
         let center = term_center();
         self.print_at(
             word, 
@@ -80,7 +84,7 @@ impl Renderer {
             clear
         )
     }
-    pub fn print_at(self: &Self, word: &str, position: (u16, u16), align: TextAlign, color: Option<Color>, background_color: Option<Color>, clear: Option<Clear>) {
+    pub fn print_at(&self, word: &str, position: (u16, u16), align: TextAlign, color: Option<Color>, background_color: Option<Color>, clear: Option<Clear>) {
         let mut stdout = stdout();
         if let Some(color) = color {
             execute!(
@@ -94,23 +98,22 @@ impl Renderer {
                 SetForegroundColor(color),
             ).expect("Could not set background colour");
         }
-        let x ;
         let y = position.1;
-        match align {
+        let x = match align {
             TextAlign::Left => {
-                x = position.0;
+                position.0
             }
             TextAlign::Center => {
-                x = position.0 - word.chars().count() as u16 / 2;
+                position.0 - word.chars().count() as u16 / 2
             }
             TextAlign::Right => {
-                x = position.0 - word.chars().count() as u16;
+                position.0 - word.chars().count() as u16
             }
-        }
+        };
         execute!(
             stdout,
             MoveTo(x, y),
-        ).expect(format!("Could not move to position {} {}", x, y).as_str());
+        ).unwrap_or_else(|_| panic!("Could not move to position {} {}", x, y));
         if let Some(clear) = clear {
             execute!(
                 stdout,
@@ -127,7 +130,7 @@ impl Renderer {
             ResetColor
         ).expect("Could not reset text colour after render");
     }
-    pub fn clear_line_at_center(self: &Self, offset: (i16, i16)) {
+    pub fn clear_line_at_center(&self, offset: (i16, i16)) {
         let center = term_center();
         self.clear_line_at(
             (
@@ -136,7 +139,7 @@ impl Renderer {
             ), 
         )
     }
-    pub fn clear_down_from_center_at(self: &Self, offset_y: i16) {
+    pub fn clear_down_from_center_at(&self, offset_y: i16) {
         let center = term_center();
         let mut stdout = stdout();
         execute!(
@@ -146,7 +149,7 @@ impl Renderer {
             Clear(ClearType::FromCursorDown)
         ).expect("Could not clear lines down from center")
     }
-    pub fn clear_line_at(self: &Self, position: (u16, u16)) {
+    pub fn clear_line_at(&self, position: (u16, u16)) {
         let mut stdout = stdout();
         execute!(
             stdout,
@@ -0,0 +1,148 @@
+//! Spaced repetition, after SM-2. Every word typed gets a grade from how
+//! well it was typed, and the grade sets when the word is due again: words
+//! typed well come back after longer and longer intervals, words typed
+//! badly come back within the session.
+
+// Ease words start with, and the least they can have
+const STARTING_EASE: f64 = 2.5;
+const MINIMUM_EASE: f64 = 1.3;
+// Words failed come back this many seconds later
+const RELEARN_DELAY: i64 = 10 * 60;
+const SECONDS_PER_DAY: f64 = 24.0 * 60.0 * 60.0;
+// Typing a word faster than this, in chars per second, is a perfect grade
+const FLUENT_SPEED: f64 = 3.0;
+
+/// When a word is due again and how fast its intervals grow.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Review {
+    /// Days until the word is due after its last review
+    pub interval: f64,
+    pub ease: f64,
+    /// Unix timestamp, in seconds, of when the word is due again
+    pub due: i64,
+    /// Times in a row the word was typed well
+    pub repetitions: u32,
+}
+
+impl Default for Review {
+    fn default() -> Self {
+        Self {
+            interval: 0.0,
+            ease: STARTING_EASE,
+            due: 0,
+            repetitions: 0,
+        }
+    }
+}
+
+impl Review {
+    /// Grades a word from 0 to 5 the way SM-2 does, from the share of
+    /// chars typed right and how fast they were typed. Grades under 3 mean
+    /// the word has to be learnt again.
+    pub fn grade(chars_typed: u64, chars_failed: u64, chars_per_second: f64) -> u8 {
+        let accuracy = 1.0 - chars_failed as f64 / chars_typed.max(1) as f64;
+        if accuracy < 0.6 {
+            1
+        } else if accuracy < 0.8 {
+            2
+        } else if accuracy < 0.95 {
+            3
+        } else if chars_per_second < FLUENT_SPEED {
+            4
+        } else {
+            5
+        }
+    }
+    /// Schedules the next review of a word typed with a grade at `now`.
+    pub fn update(&mut self, grade: u8, now: i64) {
+        let grade = grade.min(5) as f64;
+        self.ease = (self.ease + 0.1 - (5.0 - grade) * (0.08 + (5.0 - grade) * 0.02))
+            .max(MINIMUM_EASE);
+        if grade < 3.0 {
+            self.repetitions = 0;
+            self.interval = 0.0;
+            self.due = now + RELEARN_DELAY;
+            return;
+        }
+        self.repetitions += 1;
+        self.interval = match self.repetitions {
+            1 => 1.0,
+            2 => 6.0,
+            _ => self.interval.max(1.0) * self.ease,
+        };
+        self.due = now + (self.interval * SECONDS_PER_DAY) as i64;
+    }
+    pub fn is_due(&self, now: i64) -> bool {
+        self.due <= now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+    const DAY: i64 = SECONDS_PER_DAY as i64;
+
+    #[test]
+    fn grades_follow_accuracy_then_speed() {
+        assert_eq!(Review::grade(20, 0, 4.0), 5);
+        assert_eq!(Review::grade(20, 0, 1.0), 4);
+        assert_eq!(Review::grade(20, 2, 4.0), 3);
+        assert_eq!(Review::grade(10, 3, 4.0), 2);
+        assert_eq!(Review::grade(10, 5, 4.0), 1);
+        // Nothing typed counts as nothing missed
+        assert_eq!(Review::grade(0, 0, 0.0), 4);
+    }
+
+    #[test]
+    fn intervals_grow_with_every_good_review() {
+        let mut review = Review::default();
+        assert!(review.is_due(NOW));
+        let mut now = NOW;
+        for interval in [1.0, 6.0, 15.0, 37.5] {
+            review.update(4, now);
+            assert_eq!(review.interval, interval);
+            assert_eq!(review.ease, STARTING_EASE);
+            assert_eq!(review.due, now + (interval * SECONDS_PER_DAY) as i64);
+            assert!(!review.is_due(review.due - 1));
+            assert!(review.is_due(review.due));
+            now = review.due;
+        }
+        assert_eq!(review.repetitions, 4);
+    }
+
+    #[test]
+    fn ease_rises_with_perfect_grades_and_stays_above_the_minimum() {
+        let mut review = Review::default();
+        review.update(5, NOW);
+        assert!((review.ease - 2.6).abs() < 1e-9);
+        // Grades past 5 count as 5
+        review.update(9, NOW);
+        assert!((review.ease - 2.7).abs() < 1e-9);
+        for _ in 0..20 {
+            review.update(3, NOW);
+        }
+        assert_eq!(review.ease, MINIMUM_EASE);
+        review.update(0, NOW);
+        assert_eq!(review.ease, MINIMUM_EASE);
+    }
+
+    #[test]
+    fn lapses_start_the_schedule_over() {
+        let mut review = Review::default();
+        for day in 0..3 {
+            review.update(4, NOW + day * DAY);
+        }
+        review.update(1, NOW + 30 * DAY);
+        assert_eq!(review.repetitions, 0);
+        assert_eq!(review.interval, 0.0);
+        assert_eq!(review.due, NOW + 30 * DAY + RELEARN_DELAY);
+        assert!(review.ease < STARTING_EASE);
+        // The word is due again within the session, then starts at a day
+        assert!(review.is_due(NOW + 30 * DAY + RELEARN_DELAY));
+        review.update(4, NOW + 31 * DAY);
+        assert_eq!(review.interval, 1.0);
+        assert_eq!(review.repetitions, 1);
+    }
+}
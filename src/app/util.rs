@@ -6,4 +6,14 @@ pub fn term_center() -> (u16, u16) {
     let x = width / 2;
     let y = height / 2;
     (x, y)
-}
\ No newline at end of file
+}
+// Phrases are wrapped to this many columns, or less on narrow terminals
+const MAX_LINE_WIDTH: u16 = 60;
+
+/// How wide lines of text to type can be, leaving room on both sides for
+/// the wpm and the progress.
+pub fn wrap_width() -> usize {
+    let (width, _) = terminal::size()
+        .expect("Could not get terminal window size");
+    width.saturating_sub(32).clamp(10, MAX_LINE_WIDTH) as usize
+}
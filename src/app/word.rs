@@ -1,11 +1,238 @@
+use std::ops::Range;
+
 use chrono::Utc;
 
+use crate::importer::dictionary::{DictionaryPhrase, DictionaryWord};
+
+use super::input::Input;
+use super::matching::{KeyMatch, Matching};
+use super::metrics::Metrics;
+
+/// A word to type. Some words can be typed in more than one way, like the
+/// translations of reverse mode, `original` is the candidate shown, which
+/// is the first one that still matches what was typed.
 #[derive(Debug)]
 pub struct Word {
+    /// Headword in the dictionary, which the profile keeps track of
+    pub identifier: String,
     pub size: usize,
     pub original: String,
     pub original_chars: Vec<char>,
     pub translation: Vec<String>,
+    pub candidates: Vec<Vec<char>>,
+    /// Candidates that match everything typed so far
+    pub matching: Vec<usize>,
+    /// Keys typed of a substitution that isn't finished, like the first
+    /// "a" of "aa" for å
+    pub pending: String,
+    /// Whole phrases are wrapped over several lines and their progress is
+    /// counted in words
+    pub is_phrase: bool,
+    /// Chars skipped over without typing anything, which each candidate
+    /// can have in different places
+    pub skipped: Vec<char>,
+}
+
+impl From<&DictionaryWord> for Word {
+    fn from(word: &DictionaryWord) -> Self {
+        Self {
+            identifier: word.identifier.clone(),
+            size: word.identifier.chars().count(),
+            original: word.identifier.clone(),
+            original_chars: word.identifier.chars().collect(),
+            translation: word.translation.clone(),
+            candidates: vec![word.identifier.chars().collect()],
+            matching: vec![0],
+            pending: String::new(),
+            is_phrase: false,
+            skipped: Vec::new(),
+        }
+    }
+}
+
+impl Word {
+    /// A word to type the other way around: the word is shown and any of
+    /// its translations is typed.
+    pub fn reversed(word: &DictionaryWord) -> Self {
+        if word.translation.is_empty() {
+            return Self::from(word);
+        }
+        let mut reversed = Self {
+            identifier: word.identifier.clone(),
+            size: 0,
+            original: String::new(),
+            original_chars: Vec::new(),
+            translation: vec![word.identifier.clone()],
+            candidates: word.translation.iter().map(|translation| translation.chars().collect()).collect(),
+            matching: (0..word.translation.len()).collect(),
+            pending: String::new(),
+            is_phrase: false,
+            skipped: Vec::new(),
+        };
+        reversed.show_candidate(0);
+        reversed
+    }
+    /// Checks a key typed after `keys` others against the candidates still
+    /// matching, and keeps the ones it is right for. Keys no candidate
+    /// takes are misses and change nothing, keys that start a
+    /// substitution are kept until it is typed in full.
+    pub fn advance(&mut self, keys: usize, typed: char, matching: &Matching) -> KeyMatch {
+        let results = self.matching.iter()
+            .filter_map(|index| {
+                let candidate = &self.candidates[*index];
+                let expected = *candidate.get(self.key_position(candidate, keys))?;
+                Some((*index, matching.check(expected, &self.pending, typed)))
+            })
+            .collect::<Vec<(usize, KeyMatch)>>();
+        // Candidates typed right win over the ones only started
+        let best = [KeyMatch::Exact, KeyMatch::NearMiss, KeyMatch::Partial].into_iter()
+            .find(|key_match| results.iter().any(|(_, result)| result == key_match));
+        let Some(best) = best else {
+            self.pending.clear();
+            return KeyMatch::Miss;
+        };
+        let kept = results.iter()
+            .filter(|(_, result)| *result == best)
+            .map(|(index, _)| *index)
+            .collect::<Vec<usize>>();
+        if best == KeyMatch::Partial {
+            self.pending.push(typed);
+        } else {
+            self.pending.clear();
+        }
+        // A candidate typed in full is shown over longer ones it starts
+        let shown = kept.iter().copied()
+            .find(|index| {
+                let candidate = &self.candidates[*index];
+                best != KeyMatch::Partial && self.key_position(candidate, keys + 1) == candidate.len()
+            })
+            .unwrap_or(kept[0]);
+        self.matching = kept;
+        self.show_candidate(shown);
+        best
+    }
+    /// An example phrase of a word, typed in place of the word. The phrase
+    /// counts as typing the word in the profile.
+    pub fn from_phrase(word: &DictionaryWord, phrase: Phrase) -> Self {
+        let translation = if phrase.translation.is_empty() {
+            word.translation.clone()
+        } else {
+            vec![phrase.translation]
+        };
+        Self {
+            identifier: word.identifier.clone(),
+            size: phrase.size,
+            candidates: vec![phrase.original_chars.clone()],
+            original: phrase.original,
+            original_chars: phrase.original_chars,
+            translation,
+            matching: vec![0],
+            pending: String::new(),
+            is_phrase: true,
+            skipped: Vec::new(),
+        }
+    }
+    /// Splits the text to type into lines no wider than `width`, as ranges
+    /// of chars. Lines are broken at spaces, which stay at the end of the
+    /// line before the break so every char is on a line. Words are never
+    /// broken, even when they are wider than a line.
+    pub fn lines(&self, width: usize) -> Vec<Range<usize>> {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        let mut last_space = None;
+        for (index, c) in self.original_chars.iter().enumerate() {
+            if *c == ' ' {
+                last_space = Some(index);
+            }
+            if index - start >= width {
+                if let Some(space) = last_space.filter(|space| *space >= start) {
+                    lines.push(start..space + 1);
+                    start = space + 1;
+                }
+            }
+        }
+        lines.push(start..self.size);
+        lines
+    }
+    /// How many words of the text were typed in full, out of how many.
+    pub fn words_typed(&self, progress: usize) -> (usize, usize) {
+        let mut typed = 0;
+        let mut total = 0;
+        let mut end = 0;
+        for word in self.original.split(' ') {
+            end += word.chars().count();
+            if !word.is_empty() {
+                total += 1;
+                if end <= progress {
+                    typed += 1;
+                }
+            }
+            // The space after the word
+            end += 1;
+        }
+        (typed, total)
+    }
+    /// Position of the next char to type in the text shown, once `keys`
+    /// keys were typed, past the chars that are skipped. The size of the
+    /// text when all of it was typed.
+    pub fn position(&self, keys: usize) -> usize {
+        self.key_position(&self.original_chars, keys)
+    }
+    /// How many keys were typed before the char at a position of the text
+    /// shown, or None for chars that are skipped.
+    pub fn keys_before(&self, position: usize) -> Option<usize> {
+        let c = self.original_chars.get(position)?;
+        if self.skipped.contains(c) {
+            return None;
+        }
+        Some(self.original_chars[..position].iter().filter(|c| !self.skipped.contains(c)).count())
+    }
+    // Position in a candidate of the char typed with the key after `keys`
+    // others
+    fn key_position(&self, candidate: &[char], keys: usize) -> usize {
+        candidate.iter()
+            .enumerate()
+            .filter(|(_, c)| !self.skipped.contains(c))
+            .nth(keys)
+            .map_or(candidate.len(), |(position, _)| position)
+    }
+    /// Goes back to the candidates that matched before a char was typed,
+    /// when it is deleted.
+    pub fn restore(&mut self, matching: Vec<usize>) {
+        if matching.is_empty() {
+            return;
+        }
+        self.pending.clear();
+        self.show_candidate(matching[0]);
+        self.matching = matching;
+    }
+    /// Leaves the parts in parentheses out of every candidate, like the
+    /// "(sig)" of "gifta (sig)". Candidates that are nothing but parentheses
+    /// are kept whole.
+    pub fn strip_parentheses(&mut self) {
+        for candidate in self.candidates.iter_mut() {
+            let mut stripped = String::new();
+            let mut depth = 0;
+            for c in candidate.iter() {
+                match c {
+                    '(' => depth += 1,
+                    ')' if depth > 0 => depth -= 1,
+                    _ if depth == 0 => stripped.push(*c),
+                    _ => {}
+                }
+            }
+            let stripped = stripped.split_whitespace().collect::<Vec<&str>>().join(" ");
+            if !stripped.is_empty() {
+                *candidate = stripped.chars().collect();
+            }
+        }
+        self.show_candidate(self.matching[0]);
+    }
+    fn show_candidate(&mut self, index: usize) {
+        self.original_chars = self.candidates[index].clone();
+        self.original = self.original_chars.iter().collect();
+        self.size = self.original_chars.len();
+    }
 }
 
 #[derive(Debug)]
@@ -16,14 +243,52 @@ pub struct Phrase {
     pub translation: String,
 }
 
+impl From<&DictionaryPhrase> for Phrase {
+    fn from(phrase: &DictionaryPhrase) -> Self {
+        let original = typeable_text(&phrase.identifier);
+        Self {
+            size: original.chars().count(),
+            original_chars: original.chars().collect(),
+            original,
+            translation: phrase.translation.trim().to_string(),
+        }
+    }
+}
+
+/// Text with the punctuation keyboards don't have replaced by the one they
+/// do, like curly quotes by straight ones, and every run of whitespace made
+/// into a single space.
+fn typeable_text(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let mut typeable = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '‘' | '’' | '‚' | '′' => typeable.push('\''),
+            '“' | '”' | '„' | '″' | '«' | '»' => typeable.push('"'),
+            '‐' | '‑' | '‒' | '–' | '—' | '−' => typeable.push('-'),
+            '…' => typeable.push_str("..."),
+            _ => typeable.push(c),
+        }
+    }
+    typeable
+}
+
 #[derive(Debug)]
 pub struct State {
     pub progress: usize,
     pub failed: bool,
-    pub wpm: f64,
-    pub started_at: i64,
+    /// A wrong key was typed at the cursor and the right one not yet, when
+    /// errors have to be corrected before moving on
+    pub missed: bool,
     pub last_word_timestamp: i64,
     pub stats: Stats,
+    /// Stats of the word being typed, kept in the profile once it is done
+    pub word_stats: Stats,
+    /// Dead key waiting for the letter it goes on
+    pub dead_key: Option<char>,
+    /// How every char before the cursor was typed
+    pub input: Input,
+    pub metrics: Metrics,
 }
 
 impl Default for State {
@@ -32,10 +297,13 @@ impl Default for State {
         Self {
             progress: 0,
             failed: false,
-            wpm: 0.0,
-            started_at: current_time,
+            missed: false,
             last_word_timestamp: current_time,
             stats: Stats::default(),
+            word_stats: Stats::default(),
+            dead_key: None,
+            input: Input::default(),
+            metrics: Metrics::default(),
         }
     }
 }
@@ -45,4 +313,232 @@ pub struct Stats {
     pub completed: u64,
     pub chars_typed: u64,
     pub chars_failed: u64,
-}
\ No newline at end of file
+    /// Letters typed without their accent, which are taken but not right
+    pub near_misses: u64,
+    /// Wrong chars that were typed again right
+    pub errors_corrected: u64,
+    /// Wrong chars typed through and left in the text
+    pub errors_uncorrected: u64,
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::matching::{Accents, DEFAULT_SUBSTITUTIONS};
+
+    // A word typed through one of its translations, ready the way
+    // start_word gets it
+    fn reversed(translations: &[&str], matching: &Matching) -> Word {
+        let mut dictionary_word = DictionaryWord::new("verb".into());
+        dictionary_word.identifier = "skära".into();
+        dictionary_word.translation = translations.iter().map(|translation| translation.to_string()).collect();
+        let mut word = Word::reversed(&dictionary_word);
+        word.skipped = matching.punctuation.skipped.clone();
+        word
+    }
+
+    // Types keys the way stop mode does, gives how each was taken and
+    // how many keys went in
+    fn type_keys(word: &mut Word, keys: &str, matching: &Matching) -> (Vec<KeyMatch>, usize) {
+        let mut typed = 0;
+        let results = keys.chars()
+            .map(|key| {
+                let result = word.advance(typed, key, matching);
+                if matches!(result, KeyMatch::Exact | KeyMatch::NearMiss) {
+                    typed += 1;
+                }
+                result
+            })
+            .collect();
+        (results, typed)
+    }
+
+    #[test]
+    fn any_translation_can_be_typed() {
+        let matching = Matching::default();
+        let mut word = reversed(&["(to) cut", "slice"], &matching);
+        assert_eq!(word.original, "(to) cut");
+        let (results, typed) = type_keys(&mut word, "slice", &matching);
+        assert!(results.iter().all(|result| *result == KeyMatch::Exact));
+        assert_eq!(word.original, "slice");
+        assert_eq!(word.position(typed), word.size);
+    }
+
+    #[test]
+    fn translations_can_start_with_skipped_chars() {
+        let matching = Matching::default();
+        let mut word = reversed(&["slice", "(to) cut"], &matching);
+        assert_eq!(word.position(0), 0);
+        let (results, typed) = type_keys(&mut word, "to cut", &matching);
+        assert!(results.iter().all(|result| *result == KeyMatch::Exact));
+        assert_eq!(word.original, "(to) cut");
+        assert_eq!(word.position(0), 1);
+        assert_eq!(word.position(2), 4);
+        assert_eq!(word.position(typed), word.size);
+    }
+
+    #[test]
+    fn translations_typed_in_full_are_shown_over_longer_ones() {
+        let matching = Matching::default();
+        let mut word = reversed(&["cut up", "cut"], &matching);
+        let (_, typed) = type_keys(&mut word, "cut", &matching);
+        assert_eq!(word.original, "cut");
+        assert_eq!(word.position(typed), word.size);
+    }
+
+    #[test]
+    fn keys_are_counted_past_skipped_chars() {
+        let matching = Matching::default();
+        let word = reversed(&["(to) cut"], &matching);
+        assert_eq!(word.keys_before(0), None);
+        assert_eq!(word.keys_before(1), Some(0));
+        assert_eq!(word.keys_before(3), None);
+        assert_eq!(word.keys_before(5), Some(3));
+    }
+
+    // A word typed as it is written, ready the way start_word gets it
+    fn written(identifier: &str, matching: &Matching) -> Word {
+        let mut dictionary_word = DictionaryWord::new("noun".into());
+        dictionary_word.identifier = identifier.into();
+        let mut word = Word::from(&dictionary_word);
+        word.skipped = matching.punctuation.skipped.clone();
+        word
+    }
+
+    fn accents(accents: Accents) -> Matching {
+        Matching {
+            accents,
+            substitutions: DEFAULT_SUBSTITUTIONS.iter().map(|(keys, letter)| (keys.to_string(), *letter)).collect(),
+            ..Matching::default()
+        }
+    }
+
+    #[test]
+    fn base_letters_are_near_misses() {
+        let matching = accents(Accents::BaseLetter);
+        let mut word = written("två", &matching);
+        assert_eq!(type_keys(&mut word, "tva", &matching), (vec![KeyMatch::Exact, KeyMatch::Exact, KeyMatch::NearMiss], 3));
+        // The letter with its accent is still exact
+        let mut word = written("två", &matching);
+        assert_eq!(type_keys(&mut word, "två", &matching), (vec![KeyMatch::Exact; 3], 3));
+    }
+
+    #[test]
+    fn substitutions_are_typed_for_their_letter() {
+        let matching = accents(Accents::Substitute);
+        let mut word = written("går", &matching);
+        let (results, typed) = type_keys(&mut word, "gaar", &matching);
+        assert_eq!(results, [KeyMatch::Exact, KeyMatch::Partial, KeyMatch::Exact, KeyMatch::Exact]);
+        assert_eq!(typed, 3);
+        assert_eq!(word.position(typed), word.size);
+        // A key that doesn't go on with the substitution is a miss, and
+        // the substitution starts over
+        let mut word = written("går", &matching);
+        let (results, typed) = type_keys(&mut word, "gaxaa", &matching);
+        assert_eq!(results, [KeyMatch::Exact, KeyMatch::Partial, KeyMatch::Miss, KeyMatch::Partial, KeyMatch::Exact]);
+        assert_eq!(typed, 2);
+        // The letter itself can still be typed
+        let mut word = written("går", &matching);
+        assert_eq!(type_keys(&mut word, "går", &matching), (vec![KeyMatch::Exact; 3], 3));
+    }
+
+    #[test]
+    fn capitals_are_substituted_with_capitalized_sequences() {
+        let matching = accents(Accents::Substitute);
+        for keys in ["Aasa", "AAsa"] {
+            let mut word = written("Åsa", &matching);
+            assert_eq!(type_keys(&mut word, keys, &matching), (vec![KeyMatch::Partial, KeyMatch::Exact, KeyMatch::Exact, KeyMatch::Exact], 3));
+        }
+        let mut word = written("Åsa", &matching);
+        assert_eq!(type_keys(&mut word, "a", &matching), (vec![KeyMatch::Miss], 0));
+    }
+
+    #[test]
+    fn exact_accents_take_only_the_letter() {
+        let matching = accents(Accents::Exact);
+        let mut word = written("går", &matching);
+        assert_eq!(type_keys(&mut word, "gaa", &matching), (vec![KeyMatch::Exact, KeyMatch::Miss, KeyMatch::Miss], 1));
+        let mut word = written("går", &matching);
+        assert_eq!(type_keys(&mut word, "går", &matching), (vec![KeyMatch::Exact; 3], 3));
+    }
+
+    #[test]
+    fn skipped_punctuation_is_not_typed() {
+        let matching = Matching::default();
+        let mut word = written("gifta (sig)", &matching);
+        let (results, typed) = type_keys(&mut word, "gifta sig", &matching);
+        assert_eq!(results, [KeyMatch::Exact; 9]);
+        assert_eq!(word.position(typed), word.size);
+        // Typing a skipped char is a miss, the char after it is expected
+        let mut word = written("gifta (sig)", &matching);
+        let (results, typed) = type_keys(&mut word, "gifta (", &matching);
+        assert_eq!(results.last(), Some(&KeyMatch::Miss));
+        assert_eq!(typed, 6);
+    }
+
+    #[test]
+    fn separators_are_typed_with_any_key_but_letters() {
+        let matching = Matching::default();
+        for keys in ["e-post", "e post", "e_post"] {
+            let mut word = written("e-post", &matching);
+            assert_eq!(type_keys(&mut word, keys, &matching), (vec![KeyMatch::Exact; 6], 6));
+        }
+        let mut word = written("e-post", &matching);
+        assert_eq!(type_keys(&mut word, "ex", &matching), (vec![KeyMatch::Exact, KeyMatch::Miss], 1));
+    }
+
+    #[test]
+    fn exact_punctuation_has_to_be_typed() {
+        let mut matching = Matching::default();
+        matching.punctuation.make_exact("()-");
+        let mut word = written("gifta (sig)", &matching);
+        let (results, typed) = type_keys(&mut word, "gifta s", &matching);
+        assert_eq!(results.last(), Some(&KeyMatch::Miss));
+        assert_eq!(word.position(typed), 6);
+        let mut word = written("gifta (sig)", &matching);
+        let (results, typed) = type_keys(&mut word, "gifta (sig)", &matching);
+        assert_eq!(results, [KeyMatch::Exact; 11]);
+        assert_eq!(word.position(typed), word.size);
+        let mut word = written("e-post", &matching);
+        assert_eq!(type_keys(&mut word, "e ", &matching), (vec![KeyMatch::Exact, KeyMatch::Miss], 1));
+    }
+
+    fn phrase(text: &str) -> Word {
+        let mut dictionary_word = DictionaryWord::new("noun".into());
+        dictionary_word.identifier = "ord".into();
+        let mut phrase = DictionaryPhrase::new("example".into());
+        phrase.identifier = text.into();
+        Word::from_phrase(&dictionary_word, Phrase::from(&phrase))
+    }
+
+    #[test]
+    fn phrases_are_wrapped_at_spaces() {
+        let word = phrase("det var en gång ett ord");
+        let lines = word.lines(10)
+            .into_iter()
+            .map(|line| word.original_chars[line].iter().collect::<String>())
+            .collect::<Vec<String>>();
+        assert_eq!(lines, ["det var en ", "gång ett ", "ord"]);
+        // Every char is on exactly one line
+        assert_eq!(lines.concat(), word.original);
+        assert_eq!(word.lines(100), vec![0..word.size]);
+    }
+
+    #[test]
+    fn words_wider_than_a_line_are_not_broken() {
+        let word = phrase("ett sjukhusparkeringsområde här");
+        let lines = word.lines(5)
+            .into_iter()
+            .map(|line| word.original_chars[line].iter().collect::<String>())
+            .collect::<Vec<String>>();
+        assert_eq!(lines, ["ett ", "sjukhusparkeringsområde ", "här"]);
+    }
+
+    #[test]
+    fn words_of_phrases_are_counted_once_typed() {
+        let word = phrase("ett  ord till");
+        assert_eq!(word.words_typed(0), (0, 3));
+        assert_eq!(word.words_typed(3), (1, 3));
+        assert_eq!(word.words_typed(8), (2, 3));
+        assert_eq!(word.words_typed(word.size), (3, 3));
+    }
+}
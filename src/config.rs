@@ -1,16 +1,107 @@
 use std::env;
-use std::fs::{File, DirBuilder};
-use std::io::{Read, Write};
+use std::fs::{self, File, DirBuilder};
+use std::io::{ErrorKind, Read, Write};
 use std::collections::hash_map::HashMap;
 use std::path::Path;
 
-use super::util::get_index;
+use super::app::difficulty::Difficulty;
+use super::app::input::Correction;
+use super::app::matching::{Accents, Matching, Punctuation, DEFAULT_SUBSTITUTIONS};
+use super::app::review::Review;
+use super::importer::filter::WordFilter;
+use super::importer::parser::{ImportOptions, Format};
+use super::importer::parser::delimited::{Column, ColumnMapping};
+use super::importer::parser::dictd::DEFAULT_DICTD_DIR;
+use super::util::{get_index, get_value, get_values};
 
-#[derive(Debug)]
+/// Settings kept in `~/.config/typo-eq/config.txt`, used when they are not
+/// given as flags. Lines look like "difficulty = hard", and the settings of
+/// a difficulty level go after its name, like "[hard]".
+#[derive(Debug, Default)]
 pub struct ConfigFile {
-    pub dictionary_path: String,
+    pub dictionary_path: Option<String>,
     pub show_phrases: bool, 
-    pub profile: String,
+    pub profile: Option<String>,
+    pub difficulty: Option<String>,
+    pub accents: Option<String>,
+    pub substitutions: Option<String>,
+    pub skip_chars: Option<String>,
+    pub separator_chars: Option<String>,
+    pub exact_chars: Option<String>,
+    pub strip_parentheses: bool,
+    pub correction: Option<String>,
+    /// Settings that change the presets of the difficulty levels, by level
+    pub difficulties: HashMap<String, Vec<(String, String)>>,
+}
+
+impl ConfigFile {
+    /// Reads the config file, there being none is the same as it being
+    /// empty.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(format!("Could not read {} (Err: {:?})", path, err)),
+        };
+        let mut config_file = Self::default();
+        let mut section: Option<String> = None;
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                let name = line[1..line.len() - 1].trim().to_string();
+                if Difficulty::preset(&name).is_none() {
+                    return Err(format!("Unknown difficulty [{}] on line {} of {}", name, index + 1, path));
+                }
+                section = Some(name);
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("Expected \"setting = value\" on line {} of {}", index + 1, path));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            // Values can be quoted to keep spaces at their ends
+            let value = value.strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+            if let Some(section) = &section {
+                config_file.difficulties.entry(section.clone()).or_default()
+                    .push((key.to_string(), value.to_string()));
+                continue;
+            }
+            match key {
+                "dict" => config_file.dictionary_path = Some(value.to_string()),
+                "phrases" => config_file.show_phrases = value == "true",
+                "profile" => config_file.profile = Some(value.to_string()),
+                "difficulty" => config_file.difficulty = Some(value.to_string()),
+                "accents" => config_file.accents = Some(value.to_string()),
+                "substitutions" => config_file.substitutions = Some(value.to_string()),
+                "skip_chars" => config_file.skip_chars = Some(value.to_string()),
+                "separator_chars" => config_file.separator_chars = Some(value.to_string()),
+                "exact_chars" => config_file.exact_chars = Some(value.to_string()),
+                "strip_parentheses" => config_file.strip_parentheses = value == "true",
+                "correction" => config_file.correction = Some(value.to_string()),
+                _ => return Err(format!("Unknown setting \"{}\" on line {} of {}", key, index + 1, path)),
+            }
+        }
+        Ok(config_file)
+    }
+    /// The preset of a difficulty level, with the settings of the config
+    /// file applied.
+    pub fn difficulty(&self, name: &str) -> Result<Difficulty, String> {
+        let mut difficulty = Difficulty::preset(name).ok_or_else(|| format!(
+            "Unknown difficulty \"{}\", try one of: {}",
+            name,
+            Difficulty::NAMES.join(", "),
+        ))?;
+        for (key, value) in self.difficulties.get(name).into_iter().flatten() {
+            difficulty.set(key, value)
+                .map_err(|err| format!("{} in the [{}] settings of the config file", err, name))?;
+        }
+        Ok(difficulty)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -31,14 +122,13 @@ impl ProfileFile {
         let file = File::open(path.clone());
         if let Ok(mut file) = file {
             let mut contents = String::new(); 
-            if let Err(_) = file.read_to_string(&mut contents) {
+            if file.read_to_string(&mut contents).is_err() {
                 return Err("Could not read profile file to string".to_string());
             }
-            let mut lines = contents.lines();
-            println!("{}", path);
+            let lines = contents.lines();
             let mut profile_file = ProfileFile{ path, profiles: HashMap::new()};
             let mut current_profile: Option<Profile> = None;
-            while let Some(line) = lines.next() {
+            for line in lines {
                 // Profiles look like this: "[default]"
                 if line.starts_with("[") && line.ends_with("]") {
                     let profile_name = line.replace("[", "");
@@ -46,6 +136,8 @@ impl ProfileFile {
                     let new_profile = Some(Profile {
                         name: profile_name,
                         words_learnt: HashMap::new(),
+                        accuracy: HashMap::new(),
+                        reviews: HashMap::new(),
                     });
                     // If there is a profile currently in the stack, make
                     // sure to add it to the file
@@ -59,12 +151,29 @@ impl ProfileFile {
                 // with their complete count divided by a # like so:
                 // "some_word#13"
                 // The word is  "some_word" and it was completed 13 times
+                // Words typed since accuracy was saved also have the chars
+                // typed and missed on them: "some_word#13#104#6"
+                // followed by when they are reviewed next, as the interval
+                // in days, ease, due timestamp and repetitions:
+                // "some_word#13#104#6#6.5#2.36#1760000000#3"
                 if let Some(profile) = current_profile.clone() {
                     let mut profile = profile;
                     let mut line_separated = line.split("#");
                     let word = line_separated.next();
                     let count = line_separated.next();
+                    let chars_typed = line_separated.next().and_then(|count| count.parse::<u64>().ok());
+                    let chars_failed = line_separated.next().and_then(|count| count.parse::<u64>().ok());
+                    let interval = line_separated.next().and_then(|value| value.parse::<f64>().ok());
+                    let ease = line_separated.next().and_then(|value| value.parse::<f64>().ok());
+                    let due = line_separated.next().and_then(|value| value.parse::<i64>().ok());
+                    let repetitions = line_separated.next().and_then(|value| value.parse::<u32>().ok());
                     if let Some(word) = word {
+                        if let (Some(interval), Some(ease), Some(due), Some(repetitions)) = (interval, ease, due, repetitions) {
+                            profile.reviews.insert(word.to_string(), Review { interval, ease, due, repetitions });
+                        }
+                        if let (Some(chars_typed), Some(chars_failed)) = (chars_typed, chars_failed) {
+                            profile.accuracy.insert(word.to_string(), WordAccuracy { chars_typed, chars_failed });
+                        }
                         // If the word does not have a count or that count
                         // could not be parsed, default to 0
                         let count = if let Some(count) = count {
@@ -84,6 +193,8 @@ impl ProfileFile {
                 // Default if there is no profiles
                 profile_file.profiles.insert("default".to_string(), Profile {
                     words_learnt: HashMap::new(),
+                    accuracy: HashMap::new(),
+                    reviews: HashMap::new(),
                     name: "default".to_string(),
                 });
             }
@@ -92,7 +203,7 @@ impl ProfileFile {
             Err(format!("Profile files could not be opened on: {}", path.clone()))
         }
     }
-    pub fn save(self: &Self) ->  Result<(), String> {
+    pub fn save(&self) ->  Result<(), String> {
         if let Some(dir) = Path::new(&self.path).parent() {
             DirBuilder::new().recursive(true).create(dir).unwrap();
         }
@@ -110,12 +221,27 @@ impl ProfileFile {
             },
         }
     }
-    pub fn save_to_file(self: &Self, mut file: File) ->  Result<(), String> {
+    pub fn save_to_file(&self, mut file: File) ->  Result<(), String> {
         for profile in self.profiles.values() {
             file.write_all(format!("[{}]\n", profile.name).as_bytes())
                 .expect("Profile could not be saved on profile file");
             for (word, count) in &profile.words_learnt {
-                file.write_all(format!("{}#{}\n", word, count).as_bytes())
+                let accuracy = profile.accuracy.get(word);
+                let line = match (accuracy, profile.reviews.get(word)) {
+                    (_, Some(review)) => {
+                        let accuracy = accuracy.cloned().unwrap_or_default();
+                        format!(
+                            "{}#{}#{}#{}#{}#{}#{}#{}\n", word, count,
+                            accuracy.chars_typed, accuracy.chars_failed,
+                            review.interval, review.ease, review.due, review.repetitions,
+                        )
+                    }
+                    (Some(accuracy), None) => format!(
+                        "{}#{}#{}#{}\n", word, count, accuracy.chars_typed, accuracy.chars_failed
+                    ),
+                    (None, None) => format!("{}#{}\n", word, count),
+                };
+                file.write_all(line.as_bytes())
                     .expect("Could not write word into profile")
             }
         }
@@ -127,76 +253,297 @@ impl ProfileFile {
 pub struct Profile {
     pub name: String,
     pub words_learnt: HashMap<String, i64>,
+    /// Chars typed and missed on every word, over every session
+    pub accuracy: HashMap<String, WordAccuracy>,
+    /// When every word is reviewed next
+    pub reviews: HashMap<String, Review>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WordAccuracy {
+    pub chars_typed: u64,
+    pub chars_failed: u64,
+}
+
+/// A dictionary file or a directory of them, given with `--dict`.
+#[derive(Debug, Clone)]
+pub struct DictionaryPath {
+    pub path: String,
+    /// How often words are picked from it compared to other dictionaries
+    pub weight: u32,
+}
+
+impl From<&str> for DictionaryPath {
+    /// Paths can end with a weight, like "glossary.csv:3".
+    fn from(value: &str) -> Self {
+        if let Some((path, weight)) = value.rsplit_once(':') {
+            if let Ok(weight) = weight.parse::<u32>() {
+                return Self { path: path.to_string(), weight };
+            }
+        }
+        Self { path: value.to_string(), weight: 1 }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub dictionary_path: String,
+    pub dictionary_paths: Vec<DictionaryPath>,
     pub show_phrases: bool, 
     pub profile: Profile,
     pub profile_file: ProfileFile,
+    pub import_options: ImportOptions,
+    /// Directory to list the dictd databases of instead of starting
+    pub dict_dir: Option<String>,
+    /// Parse the dictionary again even if it has a cache
+    pub rebuild_cache: bool,
+    /// File ranking words by how common they are
+    pub frequency_list: Option<String>,
+    pub word_filter: WordFilter,
+    pub difficulty: Difficulty,
+    /// Type the translations of the words shown instead of the words
+    pub reverse: bool,
+    pub matching: Matching,
+    /// What happens when a wrong key is typed
+    pub correction: Correction,
+    /// Get every key as an escape code instead of text, dead keys are then
+    /// composed by the app
+    pub raw_keys: bool,
     pub debugging: bool,
 }
 
-pub fn extract_config(args: &Vec<String>) -> Result<Config, String> {
-    // Check if the dict file was set or use default
-    let has_dict = args.contains(&"--dict".to_string()) || args.contains(&"-d".to_string());
-    let dictionary_path;
-    if has_dict {
-        let index_of_dict = (get_index(&args, "--dict") + 1) as usize;
-        dictionary_path = args
-            .get(index_of_dict)
-            .unwrap_or(&"./src".to_string())
-            .to_owned();
-    } else {
+pub fn extract_config(args: &[String]) -> Result<Config, String> {
+    let home = env::var("HOME").expect("Cannot load profiles if $HOME is not set");
+    // Flags take precedence over the config file
+    let config_file = ConfigFile::load(&format!("{}/{}", home, ".config/typo-eq/config.txt"))?;
+
+    // Check if the dict files were set or use default, --dict can be
+    // given once for every dictionary to load
+    let mut dictionary_paths = get_values(args, &["--dict", "-d"]).into_iter()
+        .map(|value| DictionaryPath::from(value.as_str()))
+        .collect::<Vec<DictionaryPath>>();
+    if dictionary_paths.is_empty() {
+        if let Some(path) = &config_file.dictionary_path {
+            dictionary_paths.push(DictionaryPath::from(path.as_str()));
+        }
+    }
+    if dictionary_paths.is_empty() {
         let current_dir = env::current_dir();
         match current_dir {
             Ok(path) => {
-                dictionary_path = format!("{}{}", path.display(), "dict.xdxf");
+                dictionary_paths.push(DictionaryPath {
+                    path: path.join("dict.xdxf").display().to_string(),
+                    weight: 1,
+                });
             }
             Err(error) => return Err(error.to_string()),
         }
     }
-    let show_phrases = args.contains(&"--phrases".to_string()) || args.contains(&"-p".to_string());
+    let show_phrases = args.contains(&"--phrases".to_string())
+        || args.contains(&"-p".to_string())
+        || config_file.show_phrases;
     let debugging = args.contains(&"--debug".to_string());
+    let rebuild_cache = args.contains(&"--rebuild-cache".to_string());
+    let reverse = args.contains(&"--reverse".to_string());
+    let raw_keys = args.contains(&"--raw-keys".to_string());
+    let custom_substitutions = get_value(args, "--substitutions").or(config_file.substitutions.as_ref());
+    let substitutions = match custom_substitutions {
+        Some(text) => Matching::parse_substitutions(text)?,
+        None => DEFAULT_SUBSTITUTIONS.iter()
+            .map(|(keys, letter)| (keys.to_string(), *letter))
+            .collect(),
+    };
+    let accents = match get_value(args, "--accents").or(config_file.accents.as_ref()) {
+        Some(name) => Accents::from_name(name).ok_or_else(|| format!(
+            "Unknown accent matching \"{}\", try one of: {}",
+            name,
+            Accents::NAMES.join(", "),
+        ))?,
+        // Substitutions given are meant to be used
+        None if custom_substitutions.is_some() => Accents::Substitute,
+        None => Accents::default(),
+    };
+    // Chars given in a list are taken out of the others, so each char has
+    // one rule
+    let mut punctuation = Punctuation::default();
+    if let Some(chars) = get_value(args, "--skip-chars").or(config_file.skip_chars.as_ref()) {
+        punctuation.make_exact(chars);
+        punctuation.skipped = chars.chars().collect();
+    }
+    if let Some(chars) = get_value(args, "--separator-chars").or(config_file.separator_chars.as_ref()) {
+        punctuation.make_exact(chars);
+        punctuation.separators = chars.chars().collect();
+    }
+    if let Some(chars) = get_value(args, "--exact-chars").or(config_file.exact_chars.as_ref()) {
+        punctuation.make_exact(chars);
+    }
+    punctuation.strip_parentheses = args.contains(&"--strip-parentheses".to_string())
+        || config_file.strip_parentheses;
+    let matching = Matching { accents, substitutions, punctuation };
+    let correction = match get_value(args, "--correction").or(config_file.correction.as_ref()) {
+        Some(name) => Correction::from_name(name).ok_or_else(|| format!(
+            "Unknown correction mode \"{}\", try one of: {}",
+            name,
+            Correction::NAMES.join(", "),
+        ))?,
+        None => Correction::default(),
+    };
+    let dict_dir = if args.contains(&"--dict-dir".to_string()) {
+        Some(get_value(args, "--dict-dir")
+            .filter(|dir| !dir.starts_with('-'))
+            .cloned()
+            .unwrap_or(DEFAULT_DICTD_DIR.to_string()))
+    } else {
+        None
+    };
 
-    let has_profile = args.contains(&"--profile".to_string());
+    // Columns of CSV and TSV word lists, by position or header name
+    let column = |key: &str| get_value(args, key).map(|value| Column::from(value.as_str()));
+    let columns = ColumnMapping {
+        has_header: if args.contains(&"--no-header".to_string()) {
+            Some(false)
+        } else if args.contains(&"--header".to_string()) {
+            Some(true)
+        } else {
+            None
+        },
+        identifier: column("--word-column"),
+        translations: get_value(args, "--translation-columns")
+            .map(|value| value.split(',').map(Column::from).collect())
+            .unwrap_or_default(),
+        kind: column("--kind-column"),
+        phrase: column("--phrase-column"),
+        phrase_translation: column("--phrase-translation-column"),
+    };
+    let lang_code = get_value(args, "--lang-code").cloned();
+    let format = match get_value(args, "--format") {
+        Some(name) => Some(Format::from_name(name).ok_or_else(|| format!(
+            "Unknown dictionary format \"{}\", try one of: {}",
+            name,
+            Format::ALL.iter().map(Format::name).collect::<Vec<&str>>().join(", "),
+        ))?),
+        None => None,
+    };
+    let import_options = ImportOptions { columns, lang_code, format };
+
+    let frequency_list = get_value(args, "--frequency-list").cloned();
+    let number = |key: &str| match get_value(args, key) {
+        Some(value) => value.parse::<usize>()
+            .map(Some)
+            .map_err(|_| format!("{} takes a number, not \"{}\"", key, value)),
+        None => Ok(None),
+    };
+    let mut word_filter = WordFilter {
+        top: number("--top")?.map(|top| top.min(u32::MAX as usize) as u32),
+        min_length: number("--min-len")?,
+        max_length: number("--max-len")?,
+        single_words_only: args.contains(&"--single-words-only".to_string()),
+        ..Default::default()
+    };
+    if let Some(names) = get_value(args, "--pos") {
+        word_filter = word_filter.with_parts_of_speech(names);
+    }
+    if word_filter.top.is_some() && frequency_list.is_none() {
+        return Err("--top needs a --frequency-list to rank the words with".to_string());
+    }
+
+    let difficulty_name = get_value(args, "--difficulty")
+        .or(config_file.difficulty.as_ref())
+        .map(String::as_str)
+        .unwrap_or("normal");
+    let difficulty = config_file.difficulty(difficulty_name)?;
+
+    // Sessions without a profile given use the default one, so what was
+    // learnt and when to review it is kept either way
+    let profile_name = if args.contains(&"--profile".to_string()) {
+        let index_of_profile = (get_index(args, "--profile") + 1) as usize;
+        args.get(index_of_profile)
+            .unwrap_or(&"default".to_string())
+            .to_owned()
+    } else {
+        config_file.profile.clone().unwrap_or("default".to_string())
+    };
     let mut profile = Profile {
-        name: "default".to_string(),
+        name: profile_name.clone(),
         words_learnt: HashMap::new(),
+        accuracy: HashMap::new(),
+        reviews: HashMap::new(),
     };
     // Import profile from profile_file
-    let home = env::var("HOME").expect("Cannot load profiles if $HOME is not set");
     let path = format!("{}/{}", home, ".config/typo-eq/profiles.txt");
     let profile_file;
-    if has_profile {
-        let index_of_profile = (get_index(&args, "--profile") + 1) as usize;
-        let profile_name = args.get(index_of_profile)
-            .unwrap_or(&"default".to_string())
-            .to_owned();
-        profile.name = profile_name.clone();
-        let file = ProfileFile::load(path.clone());
-        let saved_profile = if let Ok(file) = file {
-            profile_file = file;
-            if let Some(p) = profile_file.profiles.get(&profile_name) {
-               Some(p.clone()) 
-            } else {None}
-        } else {
-            profile_file = ProfileFile::new(path);
-            None
-        };
-        if let Some(saved_profile) = saved_profile {
-            profile = saved_profile;
-        }
+    let file = ProfileFile::load(path.clone());
+    let saved_profile = if let Ok(file) = file {
+        profile_file = file;
+        profile_file.profiles.get(&profile_name).cloned()
     } else {
         profile_file = ProfileFile::new(path);
+        None
+    };
+    if let Some(saved_profile) = saved_profile {
+        profile = saved_profile;
     }
 
     Ok(Config {
-        dictionary_path,
+        dictionary_paths,
         debugging,
         show_phrases,
         profile,
         profile_file,
+        import_options,
+        dict_dir,
+        rebuild_cache,
+        frequency_list,
+        word_filter,
+        difficulty,
+        reverse,
+        matching,
+        correction,
+        raw_keys,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, contents: &str) -> Result<ConfigFile, String> {
+        let path = env::temp_dir().join(format!("typo-eq-test-{}-{}.txt", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        let config_file = ConfigFile::load(&path.display().to_string());
+        fs::remove_file(&path).unwrap();
+        config_file
+    }
+
+    #[test]
+    fn difficulty_settings_change_their_preset() {
+        let config_file = load("difficulties", "difficulty = hard\n\n[hard]\nshow_translations = true\nrarity_weight = 1.5\n\n# Comments are fine\n[easy]\nshow_history = false\n").unwrap();
+        assert_eq!(config_file.difficulty.as_deref(), Some("hard"));
+        let hard = config_file.difficulty("hard").unwrap();
+        assert!(hard.show_translations);
+        assert_eq!(hard.rarity_weight, 1.5);
+        // Settings not in the file are the preset's
+        assert_eq!(hard.length_weight, Difficulty::preset("hard").unwrap().length_weight);
+        assert!(!hard.show_history);
+        assert!(!config_file.difficulty("easy").unwrap().show_history);
+        assert_eq!(config_file.difficulty("normal").unwrap(), Difficulty::default());
+        assert!(config_file.difficulty("extreme").is_err());
+    }
+
+    #[test]
+    fn wrong_difficulty_settings_are_reported() {
+        assert!(load("unknown-level", "[extreme]\nrarity_weight = 1\n").is_err());
+        let config_file = load("bad-value", "[custom]\nrarity_weight = lots\n").unwrap();
+        let error = config_file.difficulty("custom").unwrap_err();
+        assert!(error.contains("[custom]"), "{}", error);
+        // Only the level used has to be right
+        assert!(config_file.difficulty("normal").is_ok());
+    }
+
+    #[test]
+    fn a_missing_config_file_is_empty() {
+        let path = env::temp_dir().join(format!("typo-eq-test-{}-missing.txt", std::process::id()));
+        let config_file = ConfigFile::load(&path.display().to_string()).unwrap();
+        assert!(config_file.difficulty.is_none());
+        assert_eq!(config_file.difficulty("hard").unwrap(), Difficulty::preset("hard").unwrap());
+    }
+}
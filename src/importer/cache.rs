@@ -0,0 +1,313 @@
+//! A binary copy of parsed dictionaries, so big dictionaries only have to
+//! be parsed the first time they are used.
+//!
+//! Caches live in `~/.cache/typo-eq/`, one per dictionary file and import
+//! options. A cache is only used while the dictionary file, and the files
+//! read along with it like the `.dict` of StarDict, keep the size and
+//! modification time they had when the cache was written, and while
+//! [`CACHE_VERSION`] is the same.
+
+use std::{env, fs::{self, File, DirBuilder}, io::{BufWriter, Write}, path::{Path, PathBuf}, time::UNIX_EPOCH};
+
+use super::dictionary::{Dictionary, DictionaryEntry, DictionaryPhrase, DictionaryWord};
+use super::error::ImportError;
+use super::parser::{parse_file, sibling_paths, ImportOptions};
+
+/// Bumped whenever the layout of cache files or what the parsers read
+/// changes, so older caches are parsed again instead of being misread.
+pub const CACHE_VERSION: u32 = 6;
+
+const CACHE_MAGIC: &[u8] = b"TYPOEQ-CACHE";
+
+const WORD_TAG: u8 = 0;
+const PHRASE_TAG: u8 = 1;
+
+/// Parses a dictionary, or reads it from its cache in `~/.cache/typo-eq/`
+/// when the cache is still good. Without a `$HOME` nothing is cached.
+pub fn parse_file_cached(file_path: &str, options: &ImportOptions, rebuild: bool) -> Result<Dictionary, ImportError> {
+    match default_cache_dir() {
+        Some(cache_dir) => parse_file_cached_in(&cache_dir, file_path, options, rebuild),
+        None => parse_file(file_path, options),
+    }
+}
+
+/// Where caches are kept when no other directory is given.
+fn default_cache_dir() -> Option<PathBuf> {
+    let home = env::var("HOME").ok()?;
+    Some(Path::new(&home).join(".cache/typo-eq"))
+}
+
+/// Parses a dictionary, or reads it from its cache in `cache_dir` when the
+/// cache is still good. Problems with the cache are not errors, the
+/// dictionary is just parsed again.
+pub fn parse_file_cached_in(cache_dir: &Path, file_path: &str, options: &ImportOptions, rebuild: bool) -> Result<Dictionary, ImportError> {
+    let key = CacheKey::new(file_path, options);
+    if !rebuild {
+        if let Some(dictionary) = key.as_ref().and_then(|key| key.load(cache_dir)) {
+            return Ok(dictionary);
+        }
+    }
+    let dictionary = parse_file(file_path, options)?;
+    if let Some(key) = key {
+        key.save(cache_dir, &dictionary).ok();
+    }
+    Ok(dictionary)
+}
+
+/// Size and modification time of a file a dictionary is read from.
+#[derive(Debug, PartialEq)]
+struct FileStamp {
+    path: String,
+    size: u64,
+    modified: u64,
+}
+
+impl FileStamp {
+    fn new(path: &Path) -> Option<Self> {
+        let path = fs::canonicalize(path).ok()?;
+        let metadata = fs::metadata(&path).ok()?;
+        let modified = metadata.modified().ok()?
+            .duration_since(UNIX_EPOCH).ok()?
+            .as_nanos() as u64;
+        Some(Self { path: path.display().to_string(), size: metadata.len(), modified })
+    }
+}
+
+/// What a cache was made from, written at the start of the cache file.
+#[derive(Debug, PartialEq)]
+struct CacheKey {
+    /// The dictionary file, then the files read along with it
+    files: Vec<FileStamp>,
+    options: String,
+}
+
+impl CacheKey {
+    fn new(file_path: &str, options: &ImportOptions) -> Option<Self> {
+        let mut files = vec![FileStamp::new(Path::new(file_path))?];
+        for sibling in sibling_paths(file_path) {
+            files.push(FileStamp::new(Path::new(&sibling))?);
+        }
+        Some(Self {
+            files,
+            // Options change what is parsed, like the language kept from
+            // kaikki.org files, so they are part of the key too
+            options: format!("{:?}", options),
+        })
+    }
+    fn cache_path(&self, cache_dir: &Path) -> PathBuf {
+        let name = format!("{:016x}.cache", fnv_hash(format!("{}\n{}", self.files[0].path, self.options).as_bytes()));
+        cache_dir.join(name)
+    }
+    fn load(&self, cache_dir: &Path) -> Option<Dictionary> {
+        let bytes = fs::read(self.cache_path(cache_dir)).ok()?;
+        let mut reader = CacheReader { bytes: &bytes, position: 0 };
+        if reader.take(CACHE_MAGIC.len())? != CACHE_MAGIC || reader.u32()? != CACHE_VERSION {
+            return None;
+        }
+        let count = reader.u32()?;
+        let files = (0..count)
+            .map(|_| Some(FileStamp {
+                path: reader.string()?,
+                size: reader.u64()?,
+                modified: reader.u64()?,
+            }))
+            .collect::<Option<Vec<FileStamp>>>()?;
+        let key = CacheKey { files, options: reader.string()? };
+        if &key != self {
+            return None;
+        }
+        reader.dictionary()
+    }
+    fn save(&self, cache_dir: &Path, dictionary: &Dictionary) -> std::io::Result<()> {
+        let path = self.cache_path(cache_dir);
+        DirBuilder::new().recursive(true).create(cache_dir)?;
+        // Written next to the cache first, so a half written cache is never
+        // read
+        let temp_path = path.with_extension("tmp");
+        let mut file = BufWriter::new(File::create(&temp_path)?);
+        file.write_all(CACHE_MAGIC)?;
+        file.write_all(&CACHE_VERSION.to_le_bytes())?;
+        file.write_all(&(self.files.len() as u32).to_le_bytes())?;
+        for stamp in &self.files {
+            write_string(&mut file, &stamp.path)?;
+            file.write_all(&stamp.size.to_le_bytes())?;
+            file.write_all(&stamp.modified.to_le_bytes())?;
+        }
+        write_string(&mut file, &self.options)?;
+        write_dictionary(&mut file, dictionary)?;
+        file.flush()?;
+        drop(file);
+        fs::rename(&temp_path, &path)
+    }
+}
+
+fn write_dictionary(file: &mut impl Write, dictionary: &Dictionary) -> std::io::Result<()> {
+    write_string(file, dictionary.from())?;
+    write_string(file, dictionary.to())?;
+    file.write_all(&(dictionary.entries().len() as u32).to_le_bytes())?;
+    for entry in dictionary.entries() {
+        match entry {
+            DictionaryEntry::Word(word) => {
+                file.write_all(&[WORD_TAG])?;
+                write_string(file, &word.kind)?;
+                write_string(file, &word.identifier)?;
+                write_strings(file, &word.forms)?;
+                match &word.pronunciation {
+                    Some(pronunciation) => {
+                        file.write_all(&[1])?;
+                        write_string(file, pronunciation)?;
+                    }
+                    None => file.write_all(&[0])?,
+                }
+                write_strings(file, &word.translation)?;
+            }
+            DictionaryEntry::Phrase(phrase) => {
+                file.write_all(&[PHRASE_TAG])?;
+                write_string(file, &phrase.kind)?;
+                write_string(file, &phrase.identifier)?;
+                write_string(file, &phrase.translation)?;
+                write_string(file, &phrase.example_for)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_string(file: &mut impl Write, text: &str) -> std::io::Result<()> {
+    file.write_all(&(text.len() as u32).to_le_bytes())?;
+    file.write_all(text.as_bytes())
+}
+
+fn write_strings(file: &mut impl Write, texts: &[String]) -> std::io::Result<()> {
+    file.write_all(&(texts.len() as u32).to_le_bytes())?;
+    for text in texts {
+        write_string(file, text)?;
+    }
+    Ok(())
+}
+
+/// Reads back what the `write_` functions wrote. Any read past the end or
+/// bad text means the cache is broken, and gives `None`.
+struct CacheReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> CacheReader<'a> {
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.position..self.position.checked_add(length)?)?;
+        self.position += length;
+        Some(bytes)
+    }
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+    fn string(&mut self) -> Option<String> {
+        let length = self.u32()? as usize;
+        String::from_utf8(self.take(length)?.to_vec()).ok()
+    }
+    fn strings(&mut self) -> Option<Vec<String>> {
+        let count = self.u32()?;
+        (0..count).map(|_| self.string()).collect()
+    }
+    fn dictionary(&mut self) -> Option<Dictionary> {
+        let from = self.string()?;
+        let to = self.string()?;
+        let count = self.u32()?;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            match self.u8()? {
+                WORD_TAG => {
+                    let mut word = DictionaryWord::new(self.string()?);
+                    word.identifier = self.string()?;
+                    word.forms = self.strings()?;
+                    word.pronunciation = match self.u8()? {
+                        0 => None,
+                        _ => Some(self.string()?),
+                    };
+                    word.translation = self.strings()?;
+                    entries.push(DictionaryEntry::Word(word));
+                }
+                PHRASE_TAG => {
+                    let mut phrase = DictionaryPhrase::new(self.string()?);
+                    phrase.identifier = self.string()?;
+                    phrase.translation = self.string()?;
+                    phrase.example_for = self.string()?;
+                    entries.push(DictionaryEntry::Phrase(phrase));
+                }
+                _ => return None,
+            }
+        }
+        Some(Dictionary::new(entries, from, to))
+    }
+}
+
+/// FNV-1a, used to name cache files. Unlike the hasher of the standard
+/// library it gives the same names on every build.
+fn fnv_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A directory of its own for every test, emptied first
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("typo-eq-test-{}-{}", std::process::id(), name));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn translation(dict: &Dictionary, identifier: &str) -> String {
+        dict.lookup(identifier).unwrap().translation.concat()
+    }
+
+    #[test]
+    fn changing_a_sibling_changes_the_key() {
+        let dir = test_dir("key");
+        let index = dir.join("swe-eng.index").display().to_string();
+        fs::write(&index, "hund\tA\tD\n").unwrap();
+        fs::write(dir.join("swe-eng.dict"), "dog").unwrap();
+        let options = ImportOptions::default();
+        let key = CacheKey::new(&index, &options).unwrap();
+        assert_eq!(key.files.len(), 2);
+        assert_eq!(CacheKey::new(&index, &options), Some(key));
+        let key = CacheKey::new(&index, &options).unwrap();
+        fs::write(dir.join("swe-eng.dict"), "pups").unwrap();
+        assert_ne!(CacheKey::new(&index, &options), Some(key));
+        // Siblings showing up count as a change too
+        let key = CacheKey::new(&index, &options).unwrap();
+        fs::write(dir.join("swe-eng.dict.dz"), "").unwrap();
+        assert_ne!(CacheKey::new(&index, &options), Some(key));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn changing_a_sibling_invalidates_the_cache() {
+        let dir = test_dir("cache");
+        let cache_dir = dir.join("cache");
+        let index = dir.join("swe-eng.index").display().to_string();
+        fs::write(&index, "hund\tA\tD\n").unwrap();
+        fs::write(dir.join("swe-eng.dict"), "dog").unwrap();
+        let options = ImportOptions::default();
+        let dict = parse_file_cached_in(&cache_dir, &index, &options, false).unwrap();
+        assert_eq!(translation(&dict, "hund"), "dog");
+        assert!(CacheKey::new(&index, &options).unwrap().load(&cache_dir).is_some());
+        // Only the .dict changes, the index stays as it was
+        fs::write(dir.join("swe-eng.dict"), "pup!").unwrap();
+        assert!(CacheKey::new(&index, &options).unwrap().load(&cache_dir).is_none());
+        let dict = parse_file_cached_in(&cache_dir, &index, &options, false).unwrap();
+        assert_eq!(translation(&dict, "hund"), "pup");
+        fs::remove_dir_all(&dir).ok();
+    }
+}
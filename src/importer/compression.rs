@@ -0,0 +1,50 @@
+use std::io::Read;
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use xz2::read::XzDecoder;
+
+/// Ways dictionary downloads tend to be compressed in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    /// The compression a file extension stands for, along with the
+    /// extension the file has once decompressed. Short forms like `.tgz`
+    /// stand for a compressed `.tar`.
+    pub fn from_extension(extension: &str) -> Option<(Self, Option<&'static str>)> {
+        match extension.to_lowercase().as_str() {
+            // dictzip files are gzip files with an index in the header
+            "gz" | "dz" => Some((Self::Gzip, None)),
+            "tgz" => Some((Self::Gzip, Some("tar"))),
+            "bz2" => Some((Self::Bzip2, None)),
+            "tbz" | "tbz2" => Some((Self::Bzip2, Some("tar"))),
+            "xz" => Some((Self::Xz, None)),
+            "txz" => Some((Self::Xz, Some("tar"))),
+            _ => None,
+        }
+    }
+    /// The compression of a file from its first bytes.
+    pub fn from_header(header: &[u8]) -> Option<Self> {
+        if header.starts_with(b"\x1F\x8B") {
+            Some(Self::Gzip)
+        } else if header.starts_with(b"BZh") {
+            Some(Self::Bzip2)
+        } else if header.starts_with(b"\xFD7zXZ\x00") {
+            Some(Self::Xz)
+        } else {
+            None
+        }
+    }
+    pub fn decoder<'a>(&self, reader: impl Read + 'a) -> Box<dyn Read + 'a> {
+        match self {
+            Self::Gzip => Box::new(MultiGzDecoder::new(reader)),
+            Self::Bzip2 => Box::new(MultiBzDecoder::new(reader)),
+            Self::Xz => Box::new(XzDecoder::new_multi_decoder(reader)),
+        }
+    }
+}
@@ -1,16 +1,32 @@
-use std::fmt::Display;
+use std::{collections::HashMap, fmt::Display};
 
-#[derive(Debug, Clone)]
+use rand::{Rng, distributions::{Distribution, WeightedIndex}, thread_rng};
+
+use super::frequency::FrequencyList;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum DictionaryEntry {
     Word(DictionaryWord),
     Phrase(DictionaryPhrase),
 }
 
-#[derive(Debug, Clone)]
+/// Kind of the words whose dictionary doesn't give a part of speech.
+pub const DEFAULT_WORD_KIND: &str = "word";
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct DictionaryWord {
     pub kind: String,
     pub identifier: String,
+    /// Other ways of writing the word, like inflections or spellings
+    pub forms: Vec<String>,
+    pub pronunciation: Option<String>,
     pub translation: Vec<String>,
+    /// Position of the dictionary the word came from in
+    /// [`Dictionary::sources`]
+    pub source: usize,
+    /// How common the word is, 1 being the most common. Only known when a
+    /// frequency list is given.
+    pub rank: Option<u32>,
 }
 
 impl DictionaryWord {
@@ -18,17 +34,52 @@ impl DictionaryWord {
         Self {
             kind,
             identifier: String::new(),
+            forms: Vec::new(),
+            pronunciation: None,
             translation: Vec::new(),
+            source: 0,
+            rank: None,
+        }
+    }
+    /// Adds what another entry of the same word says to this one. Kinds
+    /// that differ are both kept, like "noun, verb".
+    pub fn merge(&mut self, other: DictionaryWord) {
+        if self.kind == DEFAULT_WORD_KIND {
+            self.kind = other.kind;
+        } else if other.kind != DEFAULT_WORD_KIND && !self.kinds().any(|kind| kind == other.kind) {
+            self.kind = format!("{}, {}", self.kind, other.kind);
+        }
+        for form in other.forms {
+            if form != self.identifier && !self.forms.contains(&form) {
+                self.forms.push(form);
+            }
+        }
+        if self.pronunciation.is_none() {
+            self.pronunciation = other.pronunciation;
         }
+        self.rank = self.rank.into_iter().chain(other.rank).min();
+        for translation in other.translation {
+            if !self.translation.contains(&translation) {
+                self.translation.push(translation);
+            }
+        }
+    }
+    /// Every kind of a word, words merged from several entries may have
+    /// more than one.
+    pub fn kinds(&self) -> impl Iterator<Item = &str> {
+        self.kind.split(", ")
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct DictionaryPhrase {
     pub kind: String,
     pub identifier: String,
     pub translation: String,
     pub example_for: String,
+    /// Position of the dictionary the phrase came from in
+    /// [`Dictionary::sources`]
+    pub source: usize,
 }
 
 impl DictionaryPhrase {
@@ -38,21 +89,325 @@ impl DictionaryPhrase {
             identifier: String::new(),
             translation: String::new(),
             example_for: String::new(),
+            source: 0,
         }
     }
 }
 
+/// One of the dictionaries merged into a [`Dictionary`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DictionarySource {
+    /// Usually the name of the file it was loaded from
+    pub name: String,
+    pub from: String,
+    pub to: String,
+    /// How often words are picked from this source compared to the others
+    pub weight: u32,
+}
+
 impl Display for DictionaryWord {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}->{}", self.identifier, self.translation.concat())
     }
 }
 
-#[derive(Clone)]
+/// Every entry of a dictionary is kept once, in the order it was read in.
+/// The words and phrases are views into those entries. A dictionary can be
+/// made of several sources, each entry knows which one it came from.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Dictionary {
-    pub entries: Vec<DictionaryEntry>,
-    pub words: Vec<DictionaryWord>,
-    pub phrases: Vec<DictionaryPhrase>,
-    pub from: String,
-    pub to: String,
+    entries: Vec<DictionaryEntry>,
+    sources: Vec<DictionarySource>,
+    // Positions in `entries` of the words and of the phrases
+    word_ids: Vec<usize>,
+    phrase_ids: Vec<usize>,
+    // Positions in `entries` of the words of every source
+    source_word_ids: Vec<Vec<usize>>,
+    // Position in `entries` of every word identifier
+    word_index: HashMap<String, usize>,
+    // Positions in `entries` of the examples of every word
+    phrase_index: HashMap<String, Vec<usize>>,
+}
+
+impl Dictionary {
+    pub fn new(entries: Vec<DictionaryEntry>, from: String, to: String) -> Self {
+        let source = DictionarySource { name: String::new(), from, to, weight: 1 };
+        let mut dictionary = Self {
+            entries: Vec::with_capacity(entries.len()),
+            sources: vec![source],
+            word_ids: Vec::new(),
+            phrase_ids: Vec::new(),
+            source_word_ids: vec![Vec::new()],
+            word_index: HashMap::new(),
+            phrase_index: HashMap::new(),
+        };
+        for entry in entries {
+            dictionary.push(entry);
+        }
+        dictionary
+    }
+    /// Adds an entry to the dictionary. Words written like one that is
+    /// already in it are merged into that one, so homographs from different
+    /// articles end up with all of their translations together.
+    pub fn push(&mut self, entry: DictionaryEntry) {
+        let id = self.entries.len();
+        match entry {
+            DictionaryEntry::Word(word) => {
+                if let Some(existing_id) = self.word_index.get(&word.identifier).copied() {
+                    if let DictionaryEntry::Word(existing) = &mut self.entries[existing_id] {
+                        // The word stays in the source it was first found
+                        // in, but can also be picked for the others it is in
+                        let source_ids = &mut self.source_word_ids[word.source];
+                        if existing.source != word.source && source_ids.last() != Some(&existing_id) {
+                            source_ids.push(existing_id);
+                        }
+                        existing.merge(word);
+                    }
+                    return;
+                }
+                self.word_index.insert(word.identifier.clone(), id);
+                self.word_ids.push(id);
+                self.source_word_ids[word.source].push(id);
+                self.entries.push(DictionaryEntry::Word(word));
+            }
+            DictionaryEntry::Phrase(phrase) => {
+                if !phrase.example_for.is_empty() {
+                    self.phrase_index.entry(phrase.example_for.clone()).or_default().push(id);
+                }
+                self.phrase_ids.push(id);
+                self.entries.push(DictionaryEntry::Phrase(phrase));
+            }
+        }
+    }
+    /// Adds every entry of another dictionary to this one, keeping track
+    /// of the sources they came from.
+    pub fn extend(&mut self, other: Dictionary) {
+        let offset = self.sources.len();
+        self.sources.extend(other.sources);
+        self.source_word_ids.resize(self.sources.len(), Vec::new());
+        for mut entry in other.entries {
+            match &mut entry {
+                DictionaryEntry::Word(word) => word.source += offset,
+                DictionaryEntry::Phrase(phrase) => phrase.source += offset,
+            }
+            self.push(entry);
+        }
+    }
+    /// Names the source of a dictionary loaded from a single file.
+    pub fn with_source(mut self, name: &str, weight: u32) -> Self {
+        for source in self.sources.iter_mut() {
+            source.name = name.to_string();
+            source.weight = weight;
+        }
+        self
+    }
+    /// Ranks every word by how common it is. Words not in the list are
+    /// ranked by their most common form.
+    pub fn rank_words(&mut self, frequencies: &FrequencyList) {
+        for entry in self.entries.iter_mut() {
+            if let DictionaryEntry::Word(word) = entry {
+                word.rank = std::iter::once(&word.identifier)
+                    .chain(&word.forms)
+                    .filter_map(|form| frequencies.rank(form))
+                    .min();
+            }
+        }
+    }
+    /// Removes the words the filter doesn't keep, along with their
+    /// examples.
+    pub fn retain_words(&mut self, keep: impl Fn(&DictionaryWord) -> bool) {
+        let kept = self.entries.iter()
+            .map(|entry| match entry {
+                DictionaryEntry::Word(word) => keep(word),
+                DictionaryEntry::Phrase(phrase) => {
+                    phrase.example_for.is_empty() || self.lookup(&phrase.example_for).is_some_and(&keep)
+                }
+            })
+            .collect::<Vec<bool>>();
+        // Where every entry that is kept ends up
+        let mut new_ids = Vec::with_capacity(kept.len());
+        let mut next_id = 0;
+        for is_kept in &kept {
+            new_ids.push(is_kept.then_some(next_id));
+            next_id += *is_kept as usize;
+        }
+        let remap = |ids: &[usize]| ids.iter().filter_map(|id| new_ids[*id]).collect::<Vec<usize>>();
+        self.word_ids = remap(&self.word_ids);
+        self.phrase_ids = remap(&self.phrase_ids);
+        for ids in self.source_word_ids.iter_mut() {
+            *ids = remap(ids);
+        }
+        self.word_index.retain(|_, id| match new_ids[*id] {
+            Some(new_id) => {
+                *id = new_id;
+                true
+            }
+            None => false,
+        });
+        for ids in self.phrase_index.values_mut() {
+            *ids = remap(ids);
+        }
+        self.phrase_index.retain(|_, ids| !ids.is_empty());
+        let entries = std::mem::take(&mut self.entries);
+        self.entries = entries.into_iter()
+            .zip(kept)
+            .filter_map(|(entry, is_kept)| is_kept.then_some(entry))
+            .collect();
+    }
+    pub fn entries(&self) -> &[DictionaryEntry] {
+        &self.entries
+    }
+    pub fn sources(&self) -> &[DictionarySource] {
+        &self.sources
+    }
+    /// Language of the words, taken from the first source.
+    pub fn from(&self) -> &str {
+        &self.sources[0].from
+    }
+    /// Language of the translations, taken from the first source.
+    pub fn to(&self) -> &str {
+        &self.sources[0].to
+    }
+    pub fn words(&self) -> impl ExactSizeIterator<Item = &DictionaryWord> {
+        self.word_ids.iter().map(|id| self.word_at(*id))
+    }
+    pub fn phrases(&self) -> impl ExactSizeIterator<Item = &DictionaryPhrase> {
+        self.phrase_ids.iter().map(|id| self.phrase_at(*id))
+    }
+    pub fn lookup(&self, identifier: &str) -> Option<&DictionaryWord> {
+        self.word_index.get(identifier).map(|id| self.word_at(*id))
+    }
+    /// The example phrases of a word.
+    pub fn phrases_for(&self, identifier: &str) -> impl Iterator<Item = &DictionaryPhrase> {
+        self.phrase_index.get(identifier)
+            .into_iter()
+            .flatten()
+            .map(|id| self.phrase_at(*id))
+    }
+    /// A random word. The source is picked first, by weight, and then a
+    /// word of it.
+    pub fn random_word(&self) -> Option<&DictionaryWord> {
+        let mut rng = thread_rng();
+        let ids = &self.source_word_ids[pick_source(&self.sources, &self.source_word_ids, &mut rng)?];
+        Some(self.word_at(ids[rng.gen_range(0..ids.len())]))
+    }
+    /// Every word and phrase, to pick them from while leaving more and more
+    /// of them out.
+    pub fn word_pool(&self) -> WordPool {
+        WordPool {
+            source_word_ids: self.source_word_ids.clone(),
+            phrase_ids: self.phrase_ids.clone(),
+        }
+    }
+    // Ids come from `word_ids` or `word_index`, which only point at words
+    fn word_at(&self, id: usize) -> &DictionaryWord {
+        match &self.entries[id] {
+            DictionaryEntry::Word(word) => word,
+            DictionaryEntry::Phrase(_) => unreachable!("entry {} is not a word", id),
+        }
+    }
+    fn phrase_at(&self, id: usize) -> &DictionaryPhrase {
+        match &self.entries[id] {
+            DictionaryEntry::Phrase(phrase) => phrase,
+            DictionaryEntry::Word(_) => unreachable!("entry {} is not a phrase", id),
+        }
+    }
+}
+
+/// The words and phrases of a dictionary that can still be picked, for
+/// picking them at random among the ones a filter keeps, like the words not
+/// learnt yet. Entries the filter leaves out are dropped from the pool the
+/// first time they are picked, so the filter has to keep leaving them out,
+/// and picking costs the same however many entries were left out.
+#[derive(Debug, Clone)]
+pub struct WordPool {
+    source_word_ids: Vec<Vec<usize>>,
+    phrase_ids: Vec<usize>,
+}
+
+impl WordPool {
+    /// A random word of the pool the filter keeps, picked the way
+    /// [`Dictionary::random_word`] picks them.
+    pub fn pick<'a>(&mut self, dictionary: &'a Dictionary, filter: impl Fn(&DictionaryWord) -> bool) -> Option<&'a DictionaryWord> {
+        let mut rng = thread_rng();
+        loop {
+            let source = pick_source(&dictionary.sources, &self.source_word_ids, &mut rng)?;
+            let ids = &mut self.source_word_ids[source];
+            let position = rng.gen_range(0..ids.len());
+            let word = dictionary.word_at(ids[position]);
+            if filter(word) {
+                return Some(word);
+            }
+            // Words in several sources are dropped from each of them once
+            // they are picked there
+            ids.swap_remove(position);
+        }
+    }
+    /// A random phrase of the pool the filter keeps, every phrase being as
+    /// likely.
+    pub fn pick_phrase<'a>(&mut self, dictionary: &'a Dictionary, filter: impl Fn(&DictionaryPhrase) -> bool) -> Option<&'a DictionaryPhrase> {
+        let mut rng = thread_rng();
+        while !self.phrase_ids.is_empty() {
+            let position = rng.gen_range(0..self.phrase_ids.len());
+            let phrase = dictionary.phrase_at(self.phrase_ids[position]);
+            if filter(phrase) {
+                return Some(phrase);
+            }
+            self.phrase_ids.swap_remove(position);
+        }
+        None
+    }
+}
+
+// Picks a source with words by weight, or by how many words they have when
+// every one of them has a weight of 0. None when there are no words.
+fn pick_source(sources: &[DictionarySource], source_word_ids: &[Vec<usize>], rng: &mut impl Rng) -> Option<usize> {
+    let weights = sources.iter()
+        .zip(source_word_ids)
+        .map(|(source, ids)| if ids.is_empty() { 0 } else { source.weight });
+    match WeightedIndex::new(weights) {
+        Ok(source) => Some(source.sample(rng)),
+        Err(_) => WeightedIndex::new(source_word_ids.iter().map(Vec::len)).ok().map(|source| source.sample(rng)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dictionary(identifiers: &[&str]) -> Dictionary {
+        let entries = identifiers.iter()
+            .map(|identifier| {
+                let mut word = DictionaryWord::new(DEFAULT_WORD_KIND.to_string());
+                word.identifier = identifier.to_string();
+                DictionaryEntry::Word(word)
+            })
+            .collect();
+        Dictionary::new(entries, "Swedish".to_string(), "English".to_string())
+    }
+
+    #[test]
+    fn pools_only_give_words_the_filter_keeps() {
+        let identifiers = (0..100).map(|number| number.to_string()).collect::<Vec<String>>();
+        let dict = dictionary(&identifiers.iter().map(String::as_str).collect::<Vec<&str>>());
+        let mut pool = dict.word_pool();
+        for _ in 0..20 {
+            let word = pool.pick(&dict, |word| word.identifier == "42").unwrap();
+            assert_eq!(word.identifier, "42");
+        }
+        // Every other word was dropped along the way
+        assert_eq!(pool.source_word_ids, [dict.word_index.get("42").copied().into_iter().collect::<Vec<usize>>()]);
+        assert!(pool.pick(&dict, |_| false).is_none());
+        assert!(pool.pick(&dict, |_| true).is_none());
+    }
+
+    #[test]
+    fn sources_without_weight_are_still_picked_from() {
+        let mut dict = dictionary(&["hund"]).with_source("a", 0);
+        dict.extend(dictionary(&["katt"]).with_source("b", 0));
+        let mut pool = dict.word_pool();
+        assert!(pool.pick(&dict, |word| word.identifier == "katt").is_some());
+        assert!(dict.random_word().is_some());
+        assert!(dictionary(&[]).random_word().is_none());
+    }
 }
@@ -0,0 +1,73 @@
+use std::{fmt::Display, io};
+
+/// Where in a file something went wrong.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Position {
+    Byte(u64),
+    Line(usize),
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Byte(byte) => write!(f, "byte {}", byte),
+            Self::Line(line) => write!(f, "line {}", line),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    /// The file could not be opened or read
+    Io { path: String, source: io::Error },
+    /// There is no parser for the file
+    UnsupportedFormat(String),
+    /// The file does not follow its format
+    Malformed { position: Option<Position>, message: String },
+}
+
+impl ImportError {
+    pub fn malformed(message: impl Into<String>) -> Self {
+        Self::Malformed { position: None, message: message.into() }
+    }
+    pub fn malformed_at(position: Position, message: impl Into<String>) -> Self {
+        Self::Malformed { position: Some(position), message: message.into() }
+    }
+    pub fn io(path: &str, source: io::Error) -> Self {
+        Self::Io { path: path.to_string(), source }
+    }
+    /// Names the file an I/O error happened on, if it was not named yet.
+    pub fn with_path(self, path: &str) -> Self {
+        match self {
+            Self::Io { path: old_path, source } if old_path.is_empty() => Self::io(path, source),
+            error => error,
+        }
+    }
+}
+
+impl From<io::Error> for ImportError {
+    fn from(source: io::Error) -> Self {
+        Self::Io { path: String::new(), source }
+    }
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io { path, source } if path.is_empty() => write!(f, "Could not read the dictionary: {}", source),
+            Self::Io { path, source } => write!(f, "Could not read {}: {}", path, source),
+            Self::UnsupportedFormat(format) => write!(f, "There is no parser for {}", format),
+            Self::Malformed { position: Some(position), message } => write!(f, "Error at {}: {}", position, message),
+            Self::Malformed { position: None, message } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
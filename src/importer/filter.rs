@@ -0,0 +1,53 @@
+use super::dictionary::DictionaryWord;
+use super::util::word_kind;
+
+/// Which words of the dictionaries to practice, set with `--pos`, `--top`,
+/// `--min-len`, `--max-len` and `--single-words-only`.
+#[derive(Debug, Clone, Default)]
+pub struct WordFilter {
+    /// Parts of speech to keep, words of any other kind are left out
+    pub parts_of_speech: Vec<String>,
+    /// Only keep words ranked this high or better in the frequency list
+    pub top: Option<u32>,
+    /// Length bounds in characters
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    /// Leave out words with spaces in them
+    pub single_words_only: bool,
+}
+
+impl WordFilter {
+    /// Parts of speech can be given by name or abbreviation, like "n" or
+    /// "vb".
+    pub fn with_parts_of_speech(mut self, names: &str) -> Self {
+        self.parts_of_speech = names.split(',')
+            .filter(|name| !name.trim().is_empty())
+            .map(word_kind)
+            .collect();
+        self
+    }
+    /// Whether the filter keeps every word.
+    pub fn is_empty(&self) -> bool {
+        self.parts_of_speech.is_empty()
+            && self.top.is_none()
+            && self.min_length.is_none()
+            && self.max_length.is_none()
+            && !self.single_words_only
+    }
+    pub fn keeps(&self, word: &DictionaryWord) -> bool {
+        if !self.parts_of_speech.is_empty()
+            && !word.kinds().any(|kind| self.parts_of_speech.iter().any(|part| part == kind)) {
+            return false;
+        }
+        if let Some(top) = self.top {
+            if !word.rank.is_some_and(|rank| rank <= top) {
+                return false;
+            }
+        }
+        let length = word.identifier.chars().count();
+        if self.min_length.is_some_and(|min| length < min) || self.max_length.is_some_and(|max| length > max) {
+            return false;
+        }
+        !(self.single_words_only && word.identifier.contains(char::is_whitespace))
+    }
+}
@@ -0,0 +1,68 @@
+use std::{collections::HashMap, fs::File, io::{BufRead, BufReader, Read}};
+
+use super::error::ImportError;
+
+/// How common words are, read from a list with a word per line. Lines can
+/// hold a rank or a count next to the word, like the lists of
+/// hermitdave/FrequencyWords ("och 2384812").
+#[derive(Debug, Clone, Default)]
+pub struct FrequencyList {
+    // Rank of every word in lowercase, 1 being the most common
+    ranks: HashMap<String, u32>,
+}
+
+impl FrequencyList {
+    pub fn parse_file(file_path: &str) -> Result<Self, ImportError> {
+        let mut file = File::open(file_path)
+            .map_err(|err| ImportError::io(file_path, err))?;
+        Self::parse(&mut file).map_err(|err| err.with_path(file_path))
+    }
+    pub fn parse(reader: &mut dyn Read) -> Result<Self, ImportError> {
+        let mut lines = Vec::new();
+        for line in BufReader::new(reader).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut word = Vec::new();
+            let mut number = None;
+            for field in line.split(|c: char| c.is_whitespace() || c == ',' || c == ';') {
+                match field.parse::<u64>() {
+                    Ok(value) if number.is_none() => number = Some(value),
+                    _ if !field.is_empty() => word.push(field),
+                    _ => {}
+                }
+            }
+            if !word.is_empty() {
+                lines.push((word.join(" ").to_lowercase(), number));
+            }
+        }
+        // Numbers that go up line after line are ranks, anything else is
+        // a count and the lines are already sorted by it
+        let has_ranks = lines.iter().all(|(_, number)| number.is_some())
+            && lines.windows(2).all(|pair| pair[0].1 <= pair[1].1);
+        let mut ranks = HashMap::with_capacity(lines.len());
+        for (index, (word, number)) in lines.into_iter().enumerate() {
+            let rank = match number {
+                Some(rank) if has_ranks => rank.min(u32::MAX as u64) as u32,
+                _ => index as u32 + 1,
+            };
+            // Lists that repeat a word, with different cases or such, keep
+            // its best rank
+            ranks.entry(word).or_insert(rank);
+        }
+        Ok(Self { ranks })
+    }
+    /// Rank of a word, 1 being the most common. Words not in the list have
+    /// none.
+    pub fn rank(&self, word: &str) -> Option<u32> {
+        self.ranks.get(&word.to_lowercase()).copied()
+    }
+    pub fn len(&self) -> usize {
+        self.ranks.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ranks.is_empty()
+    }
+}
@@ -1,2 +1,8 @@
 pub mod parser;
+pub mod cache;
+pub mod compression;
 pub mod dictionary;
+pub mod error;
+pub mod filter;
+pub mod frequency;
+pub mod util;
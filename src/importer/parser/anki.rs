@@ -0,0 +1,436 @@
+use std::{fs::{self, File, OpenOptions}, io::{self, Cursor, ErrorKind, Read}, collections::HashMap, env, path::{Path, PathBuf}, process};
+
+use rand::{Rng, thread_rng};
+use rusqlite::{Connection, OpenFlags};
+use zip::ZipArchive;
+
+use crate::importer::dictionary::{DictionaryWord, DictionaryEntry, Dictionary, DEFAULT_WORD_KIND};
+use crate::importer::error::ImportError;
+use crate::importer::util::{strip_markup, split_definition, languages_from_title};
+
+use super::base::DictionaryParser;
+use super::delimited::{ColumnMapping, is_known_name};
+
+// Collections in the order they are preferred in. Newer Anki versions
+// export a compressed collection along with an old one that only holds a
+// note asking to update Anki.
+const COLLECTION_NAMES: [&str; 3] = ["collection.anki21b", "collection.anki21", "collection.anki2"];
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Parser for Anki decks exported as `.apkg` or whole collections exported
+/// as `.colpkg`. The note fields to use are picked with the same mapping
+/// used for spreadsheet columns, either by field name or position.
+pub struct AnkiParser {
+    pub fields: ColumnMapping,
+}
+
+impl AnkiParser {
+    pub fn new(fields: ColumnMapping) -> Self {
+        Self { fields }
+    }
+}
+
+impl DictionaryParser for AnkiParser {
+    fn parse(&self, reader: &mut dyn Read) -> Result<Dictionary, ImportError> {
+        // Zip archives are read from the end, so the whole package has to
+        // be at hand
+        let mut package = Vec::new();
+        reader.read_to_end(&mut package)?;
+        let mut archive = ZipArchive::new(Cursor::new(package)).map_err(|err| {
+            ImportError::malformed(format!("not a valid Anki package ({})", err))
+        })?;
+        // SQLite can only open databases from files, so the collection is
+        // unpacked to a temporary one first
+        let collection = extract_collection(&mut archive)?;
+        read_collection(&collection.path, &self.fields)
+    }
+    fn can_parse(&self, header: &[u8]) -> bool {
+        // Packages are zip archives, there is no telling them apart from
+        // other archives without opening them
+        header.starts_with(ZIP_MAGIC)
+    }
+}
+
+// Names tried for a temporary collection before giving up
+const TEMP_FILE_TRIES: usize = 16;
+
+/// A file in the temporary directory that is removed when dropped, however
+/// the import ends.
+struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Creates a file under a random name, never opening one that is
+    /// already there.
+    fn create() -> Result<(Self, File), ImportError> {
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        // Collections may be private, only the user can read them
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        for _ in 0..TEMP_FILE_TRIES {
+            let path = env::temp_dir().join(format!(
+                "typo-eq-{}-{:016x}.anki", process::id(), thread_rng().gen::<u64>()
+            ));
+            match options.open(&path) {
+                Ok(file) => return Ok((Self { path }, file)),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(ImportError::io(&path.display().to_string(), err)),
+            }
+        }
+        Err(ImportError::io(
+            &env::temp_dir().display().to_string(),
+            io::Error::new(ErrorKind::AlreadyExists, "no free name for a temporary collection"),
+        ))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+fn extract_collection(archive: &mut ZipArchive<Cursor<Vec<u8>>>) -> Result<TempFile, ImportError> {
+    let name = COLLECTION_NAMES.iter()
+        .find(|name| archive.by_name(name).is_ok())
+        .ok_or_else(|| ImportError::malformed("there is no collection in the Anki package"))?;
+    let mut collection = archive.by_name(name)
+        .map_err(|err| ImportError::malformed(format!("could not unpack {} ({})", name, err)))?;
+    let (temp_file, mut output) = TempFile::create()?;
+    let temp_path = temp_file.path.display().to_string();
+    let result = if name.ends_with('b') {
+        zstd::stream::copy_decode(&mut collection, &mut output)
+    } else {
+        io::copy(&mut collection, &mut output).map(|_| ())
+    };
+    result.map_err(|err| ImportError::io(&temp_path, err))?;
+    Ok(temp_file)
+}
+
+fn read_collection(path: &Path, fields: &ColumnMapping) -> Result<Dictionary, ImportError> {
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(collection_error)?;
+    let note_types = read_note_types(&connection)?;
+    let decks = read_decks(&connection);
+
+    let mut statement = connection.prepare(
+        "SELECT notes.mid, notes.flds, (SELECT did FROM cards WHERE cards.nid = notes.id LIMIT 1)
+        FROM notes ORDER BY notes.id"
+    ).map_err(collection_error)?;
+    let notes = statement.query_map([], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<i64>>(2)?))
+    }).map_err(collection_error)?;
+
+    let mut entries = Vec::new();
+    let mut languages: Option<(String, String)> = None;
+    let mut deck_name: Option<String> = None;
+    for note in notes {
+        let (note_type, note_fields, deck) = note.map_err(collection_error)?;
+        let field_names = note_types.get(&note_type).cloned().unwrap_or_default();
+        let note_fields = note_fields.split('\u{1f}').collect::<Vec<&str>>();
+        // Notes of types without the chosen fields are left out
+        let identifier = match fields.identifier.as_ref() {
+            Some(column) => column.position(&field_names),
+            None => Some(0),
+        };
+        let translations = if fields.translations.is_empty() {
+            vec![identifier.map(|index| index + 1)]
+        } else {
+            fields.translations.iter().map(|column| column.position(&field_names)).collect()
+        };
+        let Some(identifier) = identifier else {
+            continue;
+        };
+        let Some(translations) = translations.into_iter().collect::<Option<Vec<usize>>>() else {
+            continue;
+        };
+        let text = clean_field(note_fields.get(identifier).unwrap_or(&""));
+        if text.is_empty() {
+            continue;
+        }
+        let mut word = DictionaryWord::new(DEFAULT_WORD_KIND.to_string());
+        word.identifier = text.replace('\n', " ");
+        for index in &translations {
+            word.translation.extend(split_definition(&clean_field(note_fields.get(*index).unwrap_or(&""))));
+        }
+        entries.push(DictionaryEntry::Word(word));
+
+        // Fields named after languages, like "Swedish" and "English", say
+        // what the deck is for, otherwise the deck name has to do
+        if languages.is_none() {
+            let language = |index: usize| field_names.get(index)
+                .filter(|name| !is_generic_field(name))
+                .cloned();
+            if let (Some(from), Some(to)) = (language(identifier), translations.first().and_then(|index| language(*index))) {
+                languages = Some((from, to));
+            }
+        }
+        if deck_name.is_none() {
+            deck_name = deck.and_then(|deck| decks.get(&deck).cloned());
+        }
+    }
+    let (from, to) = languages
+        .or_else(|| deck_name.as_ref().and_then(|deck| deck.split("::").find_map(languages_from_title)))
+        .unwrap_or_else(|| (
+            deck_name.unwrap_or("Unknown".to_string()),
+            "Unknown".to_string(),
+        ));
+    Ok(Dictionary::new(entries, from, to))
+}
+
+fn collection_error(err: rusqlite::Error) -> ImportError {
+    ImportError::malformed(format!("could not read the Anki collection ({})", err))
+}
+
+/// Field names of every note type. Older collections keep them as JSON in
+/// the `col` table, newer ones have a table for them.
+fn read_note_types(connection: &Connection) -> Result<HashMap<i64, Vec<String>>, ImportError> {
+    let mut note_types: HashMap<i64, Vec<String>> = HashMap::new();
+    let fields = connection.prepare("SELECT ntid, name FROM fields ORDER BY ntid, ord")
+        .and_then(|mut statement| {
+            statement.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+                .collect::<Result<Vec<(i64, String)>, _>>()
+        });
+    if let Ok(fields) = fields {
+        for (note_type, name) in fields {
+            note_types.entry(note_type).or_default().push(name);
+        }
+        return Ok(note_types);
+    }
+    let models = connection.query_row("SELECT models FROM col", [], |row| row.get::<_, String>(0))
+        .map_err(collection_error)?;
+    let models: serde_json::Value = serde_json::from_str(&models).map_err(|err| {
+        ImportError::malformed(format!("could not read the Anki note types ({})", err))
+    })?;
+    for (id, model) in models.as_object().into_iter().flatten() {
+        let mut fields = model["flds"].as_array().cloned().unwrap_or_default();
+        fields.sort_by_key(|field| field["ord"].as_i64());
+        let names = fields.iter()
+            .map(|field| field["name"].as_str().unwrap_or_default().to_string())
+            .collect();
+        if let Ok(id) = id.parse::<i64>() {
+            note_types.insert(id, names);
+        }
+    }
+    Ok(note_types)
+}
+
+fn read_decks(connection: &Connection) -> HashMap<i64, String> {
+    let decks = connection.prepare("SELECT id, name FROM decks")
+        .and_then(|mut statement| {
+            statement.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+                .collect::<Result<HashMap<i64, String>, _>>()
+        });
+    if let Ok(decks) = decks {
+        // Newer collections separate nested decks with a control character
+        return decks.into_iter()
+            .map(|(id, name)| (id, name.replace('\u{1f}', "::")))
+            .collect();
+    }
+    let json = connection.query_row("SELECT decks FROM col", [], |row| row.get::<_, String>(0))
+        .unwrap_or_default();
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap_or_default();
+    json.as_object().into_iter().flatten()
+        .filter_map(|(id, deck)| Some((id.parse::<i64>().ok()?, deck["name"].as_str()?.to_string())))
+        .collect()
+}
+
+fn is_generic_field(name: &str) -> bool {
+    let name = name.to_lowercase();
+    is_known_name(&name) || matches!(name.as_str(), "text" | "extra")
+}
+
+/// Turns the HTML of a note field into plain text, leaving out sounds and
+/// keeping only the answer of clozes.
+fn clean_field(field: &str) -> String {
+    let mut text = strip_markup(field);
+    while let Some(start) = text.find("[sound:") {
+        let end = text[start..].find(']').map(|end| start + end + 1).unwrap_or(text.len());
+        text.replace_range(start..end, "");
+    }
+    while let Some(start) = text.find("{{c") {
+        let Some(end) = text[start..].find("}}").map(|end| start + end) else {
+            break;
+        };
+        let cloze = &text[start + 2..end];
+        let answer = cloze.split("::").nth(1).unwrap_or_default().to_string();
+        text.replace_range(start..end + 2, &answer);
+    }
+    text.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::*;
+    use crate::importer::parser::delimited::Column;
+
+    const SEPARATOR: &str = "\u{1f}";
+
+    // Notes as their note type, fields and deck
+    const NOTES: [(i64, &[&str], i64); 4] = [
+        (1, &["hund", "dog"], 10),
+        (1, &["<b>katt</b>", "cat [sound:katt.mp3]"], 10),
+        (2, &["{{c1::häst::animal}} i stallet", "horse"], 10),
+        (1, &["", "nothing"], 10),
+    ];
+
+    // A collection the way Anki 2.1.28 and older write it, with the note
+    // types and decks as JSON
+    fn old_collection(field_names: &[&str], deck: &str) -> Vec<u8> {
+        with_database("anki2", |connection| {
+            let fields = field_names.iter().enumerate()
+                .map(|(ord, name)| serde_json::json!({ "name": name, "ord": ord }))
+                .collect::<Vec<serde_json::Value>>();
+            let models = serde_json::json!({
+                "1": { "flds": fields },
+                "2": { "flds": [{ "name": "Text", "ord": 0 }, { "name": "Extra", "ord": 1 }] },
+            });
+            let decks = serde_json::json!({ "10": { "name": deck } });
+            connection.execute_batch("CREATE TABLE col (models TEXT, decks TEXT)").unwrap();
+            connection.execute("INSERT INTO col VALUES (?1, ?2)", [models.to_string(), decks.to_string()]).unwrap();
+            add_notes(connection);
+        })
+    }
+
+    // A collection the way newer Anki versions write it, with tables for
+    // note types and decks
+    fn new_collection(field_names: &[&str], deck: &str) -> Vec<u8> {
+        with_database("anki21", |connection| {
+            connection.execute_batch("
+                CREATE TABLE fields (ntid INTEGER, ord INTEGER, name TEXT);
+                CREATE TABLE decks (id INTEGER, name TEXT);
+            ").unwrap();
+            for (ord, name) in field_names.iter().enumerate() {
+                connection.execute("INSERT INTO fields VALUES (1, ?1, ?2)", rusqlite::params![ord, name]).unwrap();
+            }
+            connection.execute("INSERT INTO fields VALUES (2, 0, 'Text'), (2, 1, 'Extra')", []).unwrap();
+            connection.execute("INSERT INTO decks VALUES (10, ?1)", [deck.replace("::", SEPARATOR)]).unwrap();
+            add_notes(connection);
+        })
+    }
+
+    fn add_notes(connection: &Connection) {
+        connection.execute_batch("
+            CREATE TABLE notes (id INTEGER, mid INTEGER, flds TEXT);
+            CREATE TABLE cards (nid INTEGER, did INTEGER);
+        ").unwrap();
+        for (id, (note_type, fields, deck)) in NOTES.iter().enumerate() {
+            connection.execute("INSERT INTO notes VALUES (?1, ?2, ?3)", rusqlite::params![id, note_type, fields.join(SEPARATOR)]).unwrap();
+            connection.execute("INSERT INTO cards VALUES (?1, ?2)", rusqlite::params![id, deck]).unwrap();
+        }
+    }
+
+    // SQLite writes databases to files, which are read back whole
+    fn with_database(name: &str, fill: impl FnOnce(&Connection)) -> Vec<u8> {
+        let path = env::temp_dir().join(format!("typo-eq-test-{}-{}-{:016x}", process::id(), name, thread_rng().gen::<u64>()));
+        let connection = Connection::open(&path).unwrap();
+        fill(&connection);
+        drop(connection);
+        let database = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        database
+    }
+
+    fn package(files: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(contents).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn parse(package: Vec<u8>, fields: ColumnMapping) -> Dictionary {
+        AnkiParser::new(fields).parse(&mut package.as_slice()).unwrap()
+    }
+
+    fn words(dict: &Dictionary) -> Vec<(String, Vec<String>)> {
+        dict.words().map(|word| (word.identifier.clone(), word.translation.clone())).collect()
+    }
+
+    fn pair(identifier: &str, translation: &str) -> (String, Vec<String>) {
+        (identifier.to_string(), vec![translation.to_string()])
+    }
+
+    #[test]
+    fn reads_the_first_two_fields_of_every_note() {
+        let package = package(&[("collection.anki2", old_collection(&["Swedish", "English"], "Default"))]);
+        let dict = parse(package, ColumnMapping::default());
+        assert_eq!(words(&dict), [pair("hund", "dog"), pair("katt", "cat"), pair("häst i stallet", "horse")]);
+        assert_eq!(dict.from(), "Swedish");
+        assert_eq!(dict.to(), "English");
+    }
+
+    #[test]
+    fn picks_fields_by_name_and_skips_other_note_types() {
+        let package = package(&[("collection.anki2", old_collection(&["Swedish", "English"], "Default"))]);
+        let fields = ColumnMapping {
+            identifier: Some(Column::Name("english".to_string())),
+            translations: vec![Column::Name("Swedish".to_string())],
+            ..ColumnMapping::default()
+        };
+        let dict = parse(package, fields);
+        // The Text and Extra note is left out
+        assert_eq!(words(&dict), [pair("dog", "hund"), pair("cat", "katt"), ("nothing".to_string(), Vec::new())]);
+        assert_eq!(dict.from(), "English");
+        assert_eq!(dict.to(), "Swedish");
+    }
+
+    #[test]
+    fn picks_fields_by_position() {
+        let package = package(&[("collection.anki2", old_collection(&["Swedish", "English"], "Default"))]);
+        let fields = ColumnMapping {
+            identifier: Some(Column::Index(1)),
+            translations: vec![Column::Index(0)],
+            ..ColumnMapping::default()
+        };
+        let dict = parse(package, fields);
+        assert_eq!(words(&dict)[..2], [pair("dog", "hund"), pair("cat", "katt")]);
+        assert_eq!(words(&dict)[2], pair("horse", "häst i stallet"));
+    }
+
+    #[test]
+    fn languages_come_from_the_deck_without_named_fields() {
+        let package = package(&[("collection.anki2", old_collection(&["Front", "Back"], "Languages::Swedish-English"))]);
+        let dict = parse(package, ColumnMapping::default());
+        assert_eq!(dict.from(), "Swedish");
+        assert_eq!(dict.to(), "English");
+    }
+
+    #[test]
+    fn reads_compressed_collections_over_old_ones() {
+        let compressed = zstd::encode_all(new_collection(&["Front", "Back"], "Vocab::swe-eng").as_slice(), 0).unwrap();
+        // Packages of newer versions hold an old collection asking to
+        // update Anki too
+        let package = package(&[
+            ("collection.anki2", old_collection(&["Front", "Back"], "Please update")),
+            ("collection.anki21b", compressed),
+        ]);
+        let dict = parse(package, ColumnMapping::default());
+        assert_eq!(words(&dict), [pair("hund", "dog"), pair("katt", "cat"), pair("häst i stallet", "horse")]);
+        assert_eq!(dict.from(), "swe");
+        assert_eq!(dict.to(), "eng");
+    }
+
+    #[test]
+    fn packages_without_a_collection_are_malformed() {
+        let package = package(&[("media", b"{}".to_vec())]);
+        let result = AnkiParser::new(ColumnMapping::default()).parse(&mut package.as_slice());
+        assert!(matches!(result, Err(ImportError::Malformed { .. })));
+    }
+
+    #[test]
+    fn cleans_html_sounds_and_clozes() {
+        assert_eq!(clean_field("<div>en <i>hund</i></div>"), "en hund");
+        assert_eq!(clean_field("dog [sound:hund.mp3]"), "dog");
+        assert_eq!(clean_field("{{c1::hund}} och {{c2::katt::animal}}"), "hund och katt");
+        assert_eq!(clean_field("fish &amp; chips"), "fish & chips");
+    }
+}
@@ -1,7 +1,11 @@
-use std::fs::File;
+use std::io::Read;
 
 use crate::importer::dictionary::Dictionary;
+use crate::importer::error::ImportError;
 
 pub trait DictionaryParser {
-    fn parse(self: &Self, file: File) -> Dictionary;
+    fn parse(&self, reader: &mut dyn Read) -> Result<Dictionary, ImportError>;
+    /// Whether the first bytes of a file look like something this parser
+    /// reads, for files whose extension doesn't say.
+    fn can_parse(&self, header: &[u8]) -> bool;
 }
@@ -0,0 +1,344 @@
+use std::io::{BufReader, BufRead, Read};
+
+use csv::{ReaderBuilder, StringRecord};
+
+use crate::importer::dictionary::{DictionaryWord, DictionaryPhrase, DictionaryEntry, Dictionary, DEFAULT_WORD_KIND};
+use crate::importer::error::{ImportError, Position};
+use crate::importer::util::{split_definition, first_line, word_kind};
+
+use super::base::DictionaryParser;
+
+// Header names that are picked up without having to map them by hand
+const IDENTIFIER_NAMES: [&str; 5] = ["word", "identifier", "term", "front", "headword"];
+const TRANSLATION_NAMES: [&str; 6] = ["translation", "translations", "meaning", "definition", "back", "gloss"];
+const KIND_NAMES: [&str; 4] = ["kind", "pos", "type", "class"];
+const PHRASE_NAMES: [&str; 3] = ["phrase", "example", "sentence"];
+const PHRASE_TRANSLATION_NAMES: [&str; 3] = ["phrase_translation", "example_translation", "sentence_translation"];
+
+/// A column of a spreadsheet, either by its 0 based position or by the
+/// name in its header.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+impl Column {
+    /// Position of the column in a header, or in the list of fields of an
+    /// Anki note.
+    pub fn position(&self, names: &[String]) -> Option<usize> {
+        match self {
+            Self::Index(index) => Some(*index),
+            Self::Name(name) => names.iter().position(|cell| cell.eq_ignore_ascii_case(name)),
+        }
+    }
+}
+
+impl From<&str> for Column {
+    /// Numbers are positions counting from 1, like spreadsheets do,
+    /// anything else is a header name.
+    fn from(value: &str) -> Self {
+        match value.trim().parse::<usize>() {
+            Ok(position) => Self::Index(position.saturating_sub(1)),
+            Err(_) => Self::Name(value.trim().to_string()),
+        }
+    }
+}
+
+/// Says which columns of a CSV or TSV file go where. Columns that are not
+/// set are looked up in the header by their usual names, and whether there
+/// is a header at all is guessed from those names when it is not set.
+#[derive(Debug, Clone, Default)]
+pub struct ColumnMapping {
+    pub has_header: Option<bool>,
+    pub identifier: Option<Column>,
+    pub translations: Vec<Column>,
+    pub kind: Option<Column>,
+    pub phrase: Option<Column>,
+    pub phrase_translation: Option<Column>,
+}
+
+struct ResolvedColumns {
+    identifier: usize,
+    translations: Vec<usize>,
+    kind: Option<usize>,
+    phrase: Option<usize>,
+    phrase_translation: Option<usize>,
+}
+
+impl ColumnMapping {
+    /// Whether a row looks like a header, that is, every column in it is
+    /// named like one of the mapped or known columns. Rows that only have
+    /// some, like "back,tillbaka", are words, so headers with other names
+    /// need `--header`.
+    fn is_header(&self, row: &[String]) -> bool {
+        if let Some(has_header) = self.has_header {
+            return has_header;
+        }
+        let names = [&self.identifier, &self.kind, &self.phrase, &self.phrase_translation]
+            .into_iter()
+            .flatten()
+            .chain(self.translations.iter())
+            .filter_map(|column| match column {
+                Column::Name(name) => Some(name.to_lowercase()),
+                Column::Index(_) => None,
+            })
+            .collect::<Vec<String>>();
+        let mut cells = row.iter().filter(|cell| !cell.is_empty()).peekable();
+        cells.peek().is_some() && cells.all(|cell| {
+            let cell = cell.to_lowercase();
+            names.contains(&cell) || is_known_name(&cell)
+        })
+    }
+    fn resolve(&self, header: Option<&[String]>) -> Result<ResolvedColumns, ImportError> {
+        let find = |column: &Option<Column>, known: &[&str]| -> Result<Option<usize>, ImportError> {
+            match column {
+                Some(column) => find_column(column, header).map(Some),
+                None => Ok(header.and_then(|header| {
+                    header.iter().position(|cell| known.contains(&cell.to_lowercase().as_str()))
+                })),
+            }
+        };
+        let identifier = find(&self.identifier, &IDENTIFIER_NAMES)?.unwrap_or(0);
+        let translations = if self.translations.is_empty() {
+            let known = header.map(|header| {
+                header.iter()
+                    .enumerate()
+                    .filter(|(_, cell)| TRANSLATION_NAMES.contains(&cell.to_lowercase().as_str()))
+                    .map(|(index, _)| index)
+                    .collect::<Vec<usize>>()
+            }).unwrap_or_default();
+            // The column next to the word is the best guess there is
+            if known.is_empty() { vec![identifier + 1] } else { known }
+        } else {
+            self.translations.iter()
+                .map(|column| find_column(column, header))
+                .collect::<Result<Vec<usize>, ImportError>>()?
+        };
+        Ok(ResolvedColumns {
+            identifier,
+            translations,
+            kind: find(&self.kind, &KIND_NAMES)?,
+            phrase: find(&self.phrase, &PHRASE_NAMES)?,
+            phrase_translation: find(&self.phrase_translation, &PHRASE_TRANSLATION_NAMES)?,
+        })
+    }
+}
+
+pub(crate) fn is_known_name(name: &str) -> bool {
+    [
+        IDENTIFIER_NAMES.as_slice(),
+        TRANSLATION_NAMES.as_slice(),
+        KIND_NAMES.as_slice(),
+        PHRASE_NAMES.as_slice(),
+        PHRASE_TRANSLATION_NAMES.as_slice(),
+    ].concat().contains(&name)
+}
+
+fn find_column(column: &Column, header: Option<&[String]>) -> Result<usize, ImportError> {
+    column.position(header.unwrap_or_default()).ok_or_else(|| ImportError::malformed_at(
+        Position::Line(1),
+        format!("the column {:?} is not in the header of the word list", column),
+    ))
+}
+
+/// Parser for word lists kept in spreadsheets and saved as `.csv` or
+/// `.tsv` files.
+pub struct DelimitedParser {
+    pub delimiter: Option<u8>,
+    pub columns: ColumnMapping,
+}
+
+impl DelimitedParser {
+    /// A parser that guesses between `,` and `;`, since spreadsheet apps
+    /// in many languages save CSV files with `;`.
+    pub fn csv(columns: ColumnMapping) -> Self {
+        Self { delimiter: None, columns }
+    }
+    pub fn tsv(columns: ColumnMapping) -> Self {
+        Self { delimiter: Some(b'\t'), columns }
+    }
+}
+
+impl DictionaryParser for DelimitedParser {
+    fn parse(&self, reader: &mut dyn Read) -> Result<Dictionary, ImportError> {
+        let mut file = BufReader::new(reader);
+        // Skip the byte order mark spreadsheet apps like to add
+        let buffer = file.fill_buf()?;
+        if buffer.starts_with(b"\xEF\xBB\xBF") {
+            file.consume(3);
+        }
+        let delimiter = match self.delimiter {
+            Some(delimiter) => delimiter,
+            None => {
+                let buffer = file.fill_buf()?;
+                let first_line = buffer.split(|byte| *byte == b'\n').next().unwrap_or_default();
+                let count = |delimiter: u8| first_line.iter().filter(|byte| **byte == delimiter).count();
+                if count(b';') > count(b',') { b';' } else { b',' }
+            }
+        };
+        let mut reader = ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(file);
+
+        let mut entries = Vec::new();
+        let mut from = "Unknown".to_string();
+        let mut to = "Unknown".to_string();
+        let mut columns = None;
+        let mut record = StringRecord::new();
+        loop {
+            match reader.read_record(&mut record) {
+                Err(e) => {
+                    let position = e.position().map(|position| Position::Line(position.line() as usize));
+                    return Err(ImportError::Malformed { position, message: e.to_string() });
+                }
+                Ok(false) => break,
+                Ok(true) => {}
+            }
+            let row = record.iter().map(|cell| cell.trim().to_string()).collect::<Vec<String>>();
+            if row.iter().all(String::is_empty) {
+                continue;
+            }
+            if columns.is_none() {
+                let is_header = self.columns.is_header(&row);
+                let resolved = self.columns.resolve(if is_header { Some(&row) } else { None })?;
+                if is_header {
+                    // Headers named after languages, like "Swedish" and
+                    // "English", say what the dictionary is for
+                    let language = |index: usize| row.get(index)
+                        .filter(|name| !name.is_empty() && !is_known_name(&name.to_lowercase()))
+                        .cloned();
+                    from = language(resolved.identifier).unwrap_or(from);
+                    to = resolved.translations.first()
+                        .and_then(|index| language(*index))
+                        .unwrap_or(to);
+                }
+                columns = Some(resolved);
+                if is_header {
+                    continue;
+                }
+            }
+            let columns = columns.as_ref().unwrap();
+            let cell = |index: usize| row.get(index).map(String::as_str).unwrap_or_default();
+            let identifier = cell(columns.identifier);
+            if identifier.is_empty() {
+                continue;
+            }
+            let mut word = DictionaryWord::new(DEFAULT_WORD_KIND.to_string());
+            word.identifier = identifier.to_string();
+            if let Some(kind) = columns.kind.map(cell).filter(|kind| !kind.is_empty()) {
+                word.kind = word_kind(kind);
+            }
+            for index in &columns.translations {
+                word.translation.extend(split_definition(cell(*index)));
+            }
+            entries.push(DictionaryEntry::Word(word));
+            if let Some(text) = columns.phrase.map(cell).filter(|text| !text.is_empty()) {
+                let mut phrase = DictionaryPhrase::new("example".to_string());
+                phrase.identifier = text.to_string();
+                phrase.translation = columns.phrase_translation.map(cell).unwrap_or_default().to_string();
+                phrase.example_for = identifier.to_string();
+                entries.push(DictionaryEntry::Phrase(phrase));
+            }
+        }
+        Ok(Dictionary::new(entries, from, to))
+    }
+    fn can_parse(&self, header: &[u8]) -> bool {
+        let Some(line) = first_line(header) else {
+            return false;
+        };
+        if line.starts_with('<') || line.starts_with('{') {
+            return false;
+        }
+        match self.delimiter {
+            Some(delimiter) => line.contains(delimiter as char),
+            None => line.contains([',', ';']) && !line.contains('\t'),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str, columns: ColumnMapping) -> Dictionary {
+        DelimitedParser::csv(columns).parse(&mut text.as_bytes()).unwrap()
+    }
+
+    fn identifiers(dict: &Dictionary) -> Vec<&str> {
+        dict.words().map(|word| word.identifier.as_str()).collect()
+    }
+
+    #[test]
+    fn header_of_known_names_is_skipped() {
+        let dict = parse("word,translation,pos\nkniv,knife,noun\n", ColumnMapping::default());
+        assert_eq!(identifiers(&dict), ["kniv"]);
+        assert_eq!(dict.words().next().unwrap().kind, "noun");
+    }
+
+    #[test]
+    fn words_named_like_columns_are_kept() {
+        let dict = parse("back,tillbaka\nclass,klass\n", ColumnMapping::default());
+        assert_eq!(identifiers(&dict), ["back", "class"]);
+    }
+
+    #[test]
+    fn other_headers_need_to_be_given() {
+        let text = "Swedish,English\nkniv,knife\n";
+        let guessed = parse(text, ColumnMapping::default());
+        assert_eq!(identifiers(&guessed), ["Swedish", "kniv"]);
+        let given = parse(text, ColumnMapping { has_header: Some(true), ..Default::default() });
+        assert_eq!(identifiers(&given), ["kniv"]);
+        assert_eq!(given.from(), "Swedish");
+        assert_eq!(given.to(), "English");
+    }
+
+    fn translations<'a>(dict: &'a Dictionary, identifier: &str) -> &'a [String] {
+        &dict.lookup(identifier).unwrap().translation
+    }
+
+    #[test]
+    fn byte_order_mark_is_skipped() {
+        let dict = parse("\u{feff}word,translation\nkniv,knife\n", ColumnMapping::default());
+        // The header is only recognized without the mark in front of it
+        assert_eq!(identifiers(&dict), ["kniv"]);
+    }
+
+    #[test]
+    fn quoted_fields_keep_delimiters_and_line_breaks() {
+        let dict = parse("kniv,\"knife, blade\"\n\"gå ut\",\"go out\nleave\"\nhus,\"\"\"house\"\"\"\n", ColumnMapping::default());
+        assert_eq!(identifiers(&dict), ["kniv", "gå ut", "hus"]);
+        assert_eq!(translations(&dict, "kniv"), ["knife, blade"]);
+        // Line breaks in a field split translations like `;` does
+        assert_eq!(translations(&dict, "gå ut"), ["go out", "leave"]);
+        assert_eq!(translations(&dict, "hus"), ["\"house\""]);
+    }
+
+    #[test]
+    fn semicolons_are_picked_over_commas() {
+        let dict = parse("kniv;knife, blade;noun\nhus;house;noun\n", ColumnMapping {
+            kind: Some(Column::Index(2)),
+            ..Default::default()
+        });
+        assert_eq!(identifiers(&dict), ["kniv", "hus"]);
+        assert_eq!(translations(&dict, "kniv"), ["knife, blade"]);
+        assert_eq!(dict.lookup("hus").unwrap().kind, "noun");
+        // As many of each is a comma separated file
+        let dict = parse("kniv,knife; blade\n", ColumnMapping::default());
+        assert_eq!(translations(&dict, "kniv"), ["knife", "blade"]);
+    }
+
+    #[test]
+    fn tab_separated_lists_are_read() {
+        let text = "word\ttranslation\tpos\nkniv\tknife, blade\tnoun\nspringa\tto run\tverb\n";
+        let parser = DelimitedParser::tsv(ColumnMapping::default());
+        assert!(parser.can_parse(text.as_bytes()));
+        assert!(!DelimitedParser::csv(ColumnMapping::default()).can_parse(text.as_bytes()));
+        let dict = parser.parse(&mut text.as_bytes()).unwrap();
+        assert_eq!(identifiers(&dict), ["kniv", "springa"]);
+        assert_eq!(translations(&dict, "kniv"), ["knife, blade"]);
+        assert_eq!(dict.lookup("springa").unwrap().kind, "verb");
+    }
+}
@@ -0,0 +1,226 @@
+use std::{fs::{self, File}, io::{self, BufReader, BufRead, Read}, path::Path};
+
+use flate2::read::MultiGzDecoder;
+
+use crate::importer::dictionary::{DictionaryWord, DictionaryEntry, Dictionary, DEFAULT_WORD_KIND};
+use crate::importer::error::{ImportError, Position};
+use crate::importer::util::{split_definition, languages_from_title, Siblings, strip_sense_number, first_line, word_kind};
+
+use super::base::DictionaryParser;
+
+/// Where distributions install dictd databases.
+pub const DEFAULT_DICTD_DIR: &str = "/usr/share/dictd";
+
+// The digits dictd uses to write offsets and sizes in base 64
+const BASE64_DIGITS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Parser for dictd databases. It is given the `.index` file and reads
+/// the `.dict` (or dictzip compressed `.dict.dz`) file that sits next to it.
+pub struct DictdParser {
+    pub siblings: Siblings,
+}
+
+impl DictionaryParser for DictdParser {
+    fn parse(&self, reader: &mut dyn Read) -> Result<Dictionary, ImportError> {
+        let data = self.siblings.read(".index", &["dict", "dict.dz"])?;
+
+        let mut entries = Vec::new();
+        let mut languages = None;
+        for (index, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let position = Position::Line(index + 1);
+            let Some((headword, offset, size)) = parse_index_line(&line) else {
+                return Err(ImportError::malformed_at(position, "not a dictd index entry"));
+            };
+            let body = offset.checked_add(size).and_then(|end| data.get(offset..end)).ok_or_else(|| ImportError::malformed_at(
+                position,
+                format!("the definition of {} is past the end of the .dict file", headword),
+            ))?;
+            let body = String::from_utf8_lossy(body);
+            // Entries starting with 00-database hold information about
+            // the database itself rather than words
+            if is_info_entry(&headword) {
+                if headword.ends_with("short") {
+                    languages = languages_from_title(&info_text(&body));
+                }
+                continue;
+            }
+            let mut word = DictionaryWord::new(DEFAULT_WORD_KIND.to_string());
+            if let Some(grammar) = headword_grammar(&headword, &body) {
+                word.kind = word_kind(grammar);
+            }
+            word.translation = split_senses(&headword, &body);
+            word.identifier = headword;
+            entries.push(DictionaryEntry::Word(word));
+        }
+        let (from, to) = languages.unwrap_or(("Unknown".to_string(), "Unknown".to_string()));
+        Ok(Dictionary::new(entries, from, to))
+    }
+    fn can_parse(&self, header: &[u8]) -> bool {
+        // dictfmt always puts the information entries first, since they
+        // sort before any word
+        first_line(header)
+            .and_then(|line| parse_index_line(&line)).is_some_and(|(headword, _, _)| is_info_entry(&headword))
+    }
+}
+
+/// A dictd database found in a directory.
+#[derive(Debug, Clone)]
+pub struct DictdDatabase {
+    pub name: String,
+    pub index_path: String,
+    pub description: String,
+}
+
+/// Finds every dictd database in a directory, that is, every `.index` file
+/// with a `.dict` or `.dict.dz` file next to it.
+pub fn list_databases(dir: &str) -> Result<Vec<DictdDatabase>, String> {
+    let files = fs::read_dir(dir)
+        .map_err(|err| format!("Could not read {} (Err: {:?})", dir, err))?;
+    let mut databases = Vec::new();
+    for file in files.flatten() {
+        let path = file.path().display().to_string();
+        let Some(base) = path.strip_suffix(".index") else {
+            continue;
+        };
+        let has_data = ["dict", "dict.dz"].iter()
+            .any(|extension| Path::new(&format!("{}.{}", base, extension)).exists());
+        if !has_data {
+            continue;
+        }
+        let name = Path::new(base).file_name().unwrap_or_default().to_string_lossy().to_string();
+        let description = read_description(base).unwrap_or_default();
+        databases.push(DictdDatabase { name, index_path: path, description });
+    }
+    databases.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(databases)
+}
+
+/// Reads the short description of a database without decompressing more of
+/// it than needed, which is usually very little since it comes first.
+fn read_description(base: &str) -> Option<String> {
+    let index = File::open(format!("{}.index", base)).ok()?;
+    let (_, offset, size) = BufReader::new(index).lines()
+        .map_while(Result::ok)
+        .filter_map(|line| parse_index_line(&line))
+        .find(|(headword, _, _)| is_info_entry(headword) && headword.ends_with("short"))?;
+    let data: Box<dyn Read> = match File::open(format!("{}.dict", base)) {
+        Ok(file) => Box::new(file),
+        Err(_) => Box::new(MultiGzDecoder::new(File::open(format!("{}.dict.dz", base)).ok()?)),
+    };
+    let mut data = data.take(offset.checked_add(size)? as u64);
+    io::copy(&mut data.by_ref().take(offset as u64), &mut io::sink()).ok()?;
+    let mut body = Vec::new();
+    data.read_to_end(&mut body).ok()?;
+    Some(info_text(&String::from_utf8_lossy(&body)))
+}
+
+fn parse_index_line(line: &str) -> Option<(String, usize, usize)> {
+    let mut columns = line.split('\t');
+    let headword = columns.next()?;
+    let offset = decode_base64(columns.next()?)?;
+    let size = decode_base64(columns.next()?)?;
+    // Databases made with --index-keep-orig keep the headword as it was
+    // written in a fourth column, the first one is normalized for lookups
+    let headword = columns.next().filter(|original| !original.is_empty()).unwrap_or(headword);
+    Some((headword.to_string(), offset, size))
+}
+
+/// Reads the numbers of an index, written in base 64. Numbers too big to
+/// be offsets are None.
+fn decode_base64(value: &str) -> Option<usize> {
+    value.chars().try_fold(0usize, |number, digit| {
+        number.checked_mul(64)?.checked_add(BASE64_DIGITS.find(digit)?)
+    })
+}
+
+fn is_info_entry(headword: &str) -> bool {
+    headword.starts_with("00-database") || headword.starts_with("00database")
+}
+
+/// The text of an information entry, without the headword line dictfmt
+/// puts before it.
+fn info_text(body: &str) -> String {
+    body.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !is_info_entry(line))
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// The grammar note of the headword line of FreeDict bodies, like the "n"
+/// of "hund /hʉnd/ <n>".
+fn headword_grammar<'a>(headword: &str, body: &'a str) -> Option<&'a str> {
+    let line = body.lines().next()?.trim();
+    if !line.starts_with(headword) {
+        return None;
+    }
+    let (_, rest) = line.split_once('<')?;
+    let (grammar, _) = rest.split_once('>')?;
+    Some(grammar)
+}
+
+/// Splits the body of an entry into its senses. Numbered senses like
+/// "1. a dog" become one translation each, with the lines that follow them
+/// joined in. Bodies without numbers have a translation per line instead.
+fn split_senses(headword: &str, body: &str) -> Vec<String> {
+    let mut lines = body.lines().peekable();
+    // Bodies usually repeat the headword, with its pronunciation and such
+    if lines.peek().is_some_and(|line| line.trim().starts_with(headword)) {
+        lines.next();
+    }
+    let lines = lines
+        .map(|line| line.trim().replace(['{', '}'], ""))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<String>>();
+    let mut senses: Vec<String> = Vec::new();
+    for line in &lines {
+        match strip_sense_number(line) {
+            Some(sense) => senses.push(sense.to_string()),
+            None => {
+                if let Some(sense) = senses.last_mut() {
+                    sense.push(' ');
+                    sense.push_str(line);
+                }
+            }
+        }
+    }
+    if senses.is_empty() {
+        return lines.iter().flat_map(|line| split_definition(line)).collect();
+    }
+    senses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_offsets() {
+        assert_eq!(decode_base64("A"), Some(0));
+        assert_eq!(decode_base64("BA"), Some(64));
+        assert_eq!(decode_base64("c/"), Some(28 * 64 + 63));
+        assert_eq!(decode_base64("a!"), None);
+    }
+
+    #[test]
+    fn numbered_senses_are_translations() {
+        let body = "hund /hʉnd/ <n>\n  1. dog\n     a domestic animal\n  2. {hound}\n";
+        assert_eq!(split_senses("hund", body), ["dog a domestic animal", "hound"]);
+        assert_eq!(headword_grammar("hund", body), Some("n"));
+    }
+
+    #[test]
+    fn unnumbered_senses_are_a_translation_per_line() {
+        assert_eq!(split_senses("katt", "katt\n  cat; puss\n  tomcat\n"), ["cat", "puss", "tomcat"]);
+        assert_eq!(headword_grammar("katt", "katt\n  cat"), None);
+    }
+
+    #[test]
+    fn oversized_offsets_are_not_entries() {
+        let oversized = "/".repeat(64);
+        assert_eq!(decode_base64(&oversized), None);
+        assert_eq!(parse_index_line(&format!("hund\t{}\tc", oversized)), None);
+        assert_eq!(parse_index_line("hund\tBA\tc"), Some(("hund".to_string(), 64, 28)));
+    }
+}
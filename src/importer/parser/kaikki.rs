@@ -0,0 +1,180 @@
+use std::io::{BufReader, BufRead, Read};
+
+use serde_json::Value;
+
+use crate::importer::dictionary::{DictionaryWord, DictionaryPhrase, DictionaryEntry, Dictionary, DEFAULT_WORD_KIND};
+use crate::importer::error::{ImportError, Position};
+use crate::importer::util::{first_line, word_kind};
+
+use super::base::DictionaryParser;
+
+/// Parser for the Wiktionary extracts published by kaikki.org as JSON
+/// Lines. Lines that aren't JSON are reported, while entries without a
+/// word or glosses are skipped. The files are read one entry at a time, so only the entries that
+/// are kept have to fit in memory.
+pub struct KaikkiParser {
+    /// Only keep entries of this language, like "sv" or "de"
+    pub lang_code: Option<String>,
+}
+
+impl KaikkiParser {
+    pub fn new(lang_code: Option<String>) -> Self {
+        Self { lang_code }
+    }
+}
+
+impl DictionaryParser for KaikkiParser {
+    fn parse(&self, reader: &mut dyn Read) -> Result<Dictionary, ImportError> {
+        let file = BufReader::new(reader);
+        let mut entries = Vec::new();
+        let mut from = None;
+        for (index, line) in file.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: Value = serde_json::from_str(&line)
+                .map_err(|err| ImportError::malformed_at(Position::Line(index + 1), err.to_string()))?;
+            if let Some(lang_code) = &self.lang_code {
+                if entry["lang_code"].as_str() != Some(lang_code.as_str()) {
+                    continue;
+                }
+            }
+            let Some(identifier) = entry["word"].as_str() else {
+                continue;
+            };
+            if from.is_none() {
+                from = entry["lang"].as_str().map(str::to_string);
+            }
+            let mut word = DictionaryWord::new(
+                entry["pos"].as_str().map(word_kind).unwrap_or(DEFAULT_WORD_KIND.to_string())
+            );
+            word.identifier = identifier.to_string();
+            // Forms tagged like this are the headers and templates of the
+            // inflection table, not words
+            for form in entry["forms"].as_array().into_iter().flatten() {
+                let is_table_note = form["tags"].as_array().into_iter().flatten()
+                    .any(|tag| matches!(tag.as_str(), Some("table-tags" | "inflection-template" | "class")));
+                let Some(text) = form["form"].as_str().map(str::trim) else {
+                    continue;
+                };
+                if is_table_note || text.is_empty() || text == identifier || word.forms.iter().any(|known| known == text) {
+                    continue;
+                }
+                word.forms.push(text.to_string());
+            }
+            let mut word_phrases = Vec::new();
+            for sense in entry["senses"].as_array().into_iter().flatten() {
+                // Glosses of sub-senses repeat the gloss of their parent
+                for gloss in sense["glosses"].as_array().into_iter().flatten() {
+                    if let Some(gloss) = gloss.as_str() {
+                        if !word.translation.iter().any(|translation| translation == gloss) {
+                            word.translation.push(gloss.to_string());
+                        }
+                    }
+                }
+                for example in sense["examples"].as_array().into_iter().flatten() {
+                    let Some(text) = example["text"].as_str() else {
+                        continue;
+                    };
+                    let mut phrase = DictionaryPhrase::new("example".to_string());
+                    phrase.identifier = text.to_string();
+                    phrase.translation = example["english"].as_str()
+                        .or(example["translation"].as_str())
+                        .unwrap_or_default()
+                        .to_string();
+                    phrase.example_for = word.identifier.clone();
+                    word_phrases.push(phrase);
+                }
+            }
+            // Some entries only hold pronunciations or etymologies
+            if word.translation.is_empty() {
+                continue;
+            }
+            entries.push(DictionaryEntry::Word(word));
+            for phrase in word_phrases {
+                entries.push(DictionaryEntry::Phrase(phrase));
+            }
+        }
+        let from = from.unwrap_or("Unknown".to_string());
+        // kaikki.org extracts the English Wiktionary, so every gloss is in
+        // English whatever the language of the words is
+        let to = "English".to_string();
+        Ok(Dictionary::new(entries, from, to))
+    }
+    fn can_parse(&self, header: &[u8]) -> bool {
+        first_line(header)
+            .is_some_and(|line| line.trim_start().starts_with('{') && line.contains("\"word\""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINES: &str = r#"{"word": "hund", "lang": "Swedish", "lang_code": "sv", "pos": "noun", "forms": [{"form": "no-table-tags", "tags": ["table-tags"]}, {"form": "hundar", "tags": ["plural"]}, {"form": "hunden", "tags": ["definite"]}, {"form": "hundar", "tags": ["indefinite", "plural"]}], "senses": [{"glosses": ["dog"], "examples": [{"text": "Hunden skäller.", "english": "The dog barks."}]}, {"glosses": ["dog", "hound (hunting dog)"]}]}
+{"word": "springa", "lang": "Swedish", "lang_code": "sv", "pos": "verb", "senses": [{"glosses": ["to run"], "examples": [{"text": "Hon springer.", "translation": "She runs."}, {"ref": "no text"}]}]}
+{"word": "Hund", "lang": "German", "lang_code": "de", "pos": "noun", "senses": [{"glosses": ["dog"]}]}
+{"word": "snabbt", "lang": "Swedish", "lang_code": "sv", "pos": "adv", "sounds": [{"ipa": "/snabt/"}]}
+{"lang": "Swedish", "lang_code": "sv", "senses": [{"glosses": ["no word"]}]}
+"#;
+
+    fn parse(lines: &str, lang_code: Option<&str>) -> Result<Dictionary, ImportError> {
+        KaikkiParser::new(lang_code.map(str::to_string)).parse(&mut lines.as_bytes())
+    }
+
+    #[test]
+    fn senses_are_translations_and_pos_is_the_kind() {
+        let dict = parse(LINES, Some("sv")).unwrap();
+        let words = dict.words().collect::<Vec<&DictionaryWord>>();
+        // Entries without a word or glosses are skipped
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[0].identifier, "hund");
+        assert_eq!(words[0].kind, "noun");
+        assert_eq!(words[0].translation, ["dog", "hound (hunting dog)"]);
+        assert_eq!(words[1].kind, "verb");
+        assert_eq!(words[1].translation, ["to run"]);
+        assert_eq!((dict.from(), dict.to()), ("Swedish", "English"));
+    }
+
+    #[test]
+    fn forms_leave_out_table_notes_and_repeats() {
+        let dict = parse(LINES, Some("sv")).unwrap();
+        assert_eq!(dict.lookup("hund").unwrap().forms, ["hundar", "hunden"]);
+    }
+
+    #[test]
+    fn examples_are_phrases_of_their_word() {
+        let dict = parse(LINES, Some("sv")).unwrap();
+        let phrases = dict.phrases().collect::<Vec<&DictionaryPhrase>>();
+        assert_eq!(phrases.len(), 2);
+        assert_eq!((phrases[0].identifier.as_str(), phrases[0].translation.as_str()), ("Hunden skäller.", "The dog barks."));
+        assert_eq!(phrases[0].example_for, "hund");
+        assert_eq!((phrases[1].identifier.as_str(), phrases[1].translation.as_str()), ("Hon springer.", "She runs."));
+        assert_eq!(phrases[1].example_for, "springa");
+    }
+
+    #[test]
+    fn every_language_is_kept_without_a_lang_code() {
+        let dict = parse(LINES, None).unwrap();
+        assert_eq!(dict.words().len(), 3);
+        assert!(dict.lookup("Hund").is_some());
+    }
+
+    #[test]
+    fn lines_that_are_not_json_are_reported() {
+        let lines = "{\"word\": \"hund\", \"senses\": [{\"glosses\": [\"dog\"]}]}\n\n{\"word\": \"katt\",\n";
+        match parse(lines, None) {
+            Err(ImportError::Malformed { position, .. }) => assert_eq!(position, Some(Position::Line(3))),
+            result => panic!("expected a malformed line, got {:?}", result.map(|dict| dict.words().len())),
+        }
+    }
+
+    #[test]
+    fn only_json_lines_with_words_can_be_parsed() {
+        let parser = KaikkiParser::new(None);
+        assert!(parser.can_parse(LINES.as_bytes()));
+        assert!(!parser.can_parse(b"[{\"word\": \"hund\"}]"));
+        assert!(!parser.can_parse(b"hund\tdog\n"));
+    }
+}
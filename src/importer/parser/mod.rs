@@ -1,32 +1,333 @@
-use std::fs::File;
+use std::{collections::HashMap, fs::{self, File}, io::{Cursor, Read}, path::Path};
 
 use self::base::DictionaryParser;
 
+use super::compression::Compression;
 use super::dictionary::Dictionary;
+use super::error::ImportError;
+use super::util::{xml_root_name, Siblings};
 
 pub mod base;
 pub mod xdxf;
+pub mod tei;
+pub mod stardict;
+pub mod delimited;
+pub mod anki;
+pub mod dictd;
+pub mod kaikki;
 pub mod typo;
 
-pub fn parse_file(file_path: &str) -> Dictionary {
-    // Extract extension to determine which parser to use
-    let (_name, extension) = file_path.split_at(
-        file_path.rfind(".")
-        .expect("Could not extract file extension to decide on dictionary parser")
-    );
+// How much of a file is read to tell its format
+const HEADER_SIZE: u64 = 4096;
+
+/// Options for the parsers that can't tell everything from the file alone.
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    /// Columns of word lists, also used for the fields of Anki notes
+    pub columns: delimited::ColumnMapping,
+    /// Language of the entries to keep from multilingual files
+    pub lang_code: Option<String>,
+    /// Parser to use whatever the file looks like
+    pub format: Option<Format>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Xdxf,
+    Tei,
+    StarDict,
+    Dictd,
+    Kaikki,
+    Csv,
+    Tsv,
+    Anki,
+    Typo,
+}
+
+impl Format {
+    /// Every format, in the order they are tried in when sniffing. Formats
+    /// that are easy to mistake for others come last.
+    pub const ALL: [Format; 9] = [
+        Self::Anki,
+        Self::Xdxf,
+        Self::Tei,
+        Self::StarDict,
+        Self::Kaikki,
+        Self::Typo,
+        Self::Dictd,
+        Self::Tsv,
+        Self::Csv,
+    ];
+
+    /// The format named in `--format`, either by its name or its usual
+    /// extension.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim_start_matches('.').to_lowercase().as_str() {
+            "xdxf" => Some(Self::Xdxf),
+            "tei" => Some(Self::Tei),
+            "stardict" | "ifo" => Some(Self::StarDict),
+            "dictd" | "index" => Some(Self::Dictd),
+            "kaikki" | "jsonl" => Some(Self::Kaikki),
+            "csv" => Some(Self::Csv),
+            "tsv" => Some(Self::Tsv),
+            "anki" | "apkg" | "colpkg" => Some(Self::Anki),
+            "typo" => Some(Self::Typo),
+            _ => None,
+        }
+    }
+    /// The format a file extension stands for. Extensions used by more than
+    /// one format, like `.xml`, stand for none.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "xdxf" => Some(Self::Xdxf),
+            "tei" => Some(Self::Tei),
+            "ifo" => Some(Self::StarDict),
+            "index" => Some(Self::Dictd),
+            "jsonl" => Some(Self::Kaikki),
+            "csv" => Some(Self::Csv),
+            "tsv" => Some(Self::Tsv),
+            "apkg" | "colpkg" => Some(Self::Anki),
+            "typo" => Some(Self::Typo),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Xdxf => "xdxf",
+            Self::Tei => "tei",
+            Self::StarDict => "stardict",
+            Self::Dictd => "dictd",
+            Self::Kaikki => "kaikki",
+            Self::Csv => "csv",
+            Self::Tsv => "tsv",
+            Self::Anki => "anki",
+            Self::Typo => "typo",
+        }
+    }
+    /// The parser of the format. Formats made of several files read the
+    /// ones that go with the dictionary from `siblings`.
+    pub fn parser(&self, options: &ImportOptions, siblings: &Siblings) -> Box<dyn DictionaryParser> {
+        match self {
+            Self::Xdxf => Box::new(xdxf::XDXFParser),
+            Self::Tei => Box::new(tei::TEIParser),
+            Self::StarDict => Box::new(stardict::StarDictParser { siblings: siblings.clone() }),
+            Self::Dictd => Box::new(dictd::DictdParser { siblings: siblings.clone() }),
+            Self::Kaikki => Box::new(kaikki::KaikkiParser::new(options.lang_code.clone())),
+            Self::Csv => Box::new(delimited::DelimitedParser::csv(options.columns.clone())),
+            Self::Tsv => Box::new(delimited::DelimitedParser::tsv(options.columns.clone())),
+            Self::Anki => Box::new(anki::AnkiParser::new(options.columns.clone())),
+            Self::Typo => Box::new(typo::TypoEQParser),
+        }
+    }
+}
+
+pub fn parse_file(file_path: &str, options: &ImportOptions) -> Result<Dictionary, ImportError> {
     let file = File::open(file_path)
-        .expect("Could not load the dictionary file");
-    match extension {
-        ".xdxf" => {
-            let parser = xdxf::XDXFParser;
-            parser.parse(file)
+        .map_err(|err| ImportError::io(file_path, err))?;
+    parse_reader(Box::new(file), file_path, options)
+        .map_err(|err| err.with_path(file_path))
+}
+
+/// Parses a dictionary from anything it can be read from. The path is only
+/// used to tell the format and to find the files that go with it.
+pub fn parse_reader(reader: Box<dyn Read + '_>, file_path: &str, options: &ImportOptions) -> Result<Dictionary, ImportError> {
+    parse_with_siblings(reader, options, &Siblings::on_disk(file_path))
+}
+
+fn parse_with_siblings(reader: Box<dyn Read + '_>, options: &ImportOptions, siblings: &Siblings) -> Result<Dictionary, ImportError> {
+    let (header, mut reader) = read_header(reader)?;
+    let file_path = siblings.path();
+    let extension = Path::new(file_path).extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    // Compressed files are parsed as the file they hold, which is the same
+    // path without the compression suffix
+    if let Some((compression, inner_extension)) = extension.as_deref().and_then(Compression::from_extension) {
+        let inner_path = Path::new(file_path).with_extension(inner_extension.unwrap_or_default());
+        return parse_with_siblings(compression.decoder(reader), options, &siblings.for_path(&inner_path.display().to_string()));
+    }
+    if let Some(compression) = Compression::from_header(&header) {
+        return parse_with_siblings(compression.decoder(reader), options, siblings);
+    }
+    if extension.as_deref() == Some("tar") || header.get(257..262) == Some(b"ustar") {
+        return parse_tar(reader, options);
+    }
+    // Use the format asked for, then the one the extension says and only
+    // then look at what is in the file
+    let format = options.format
+        .or_else(|| extension.as_deref().and_then(Format::from_extension))
+        .or_else(|| detect_format(&header, file_path, options))
+        .ok_or_else(|| unsupported_format(&header, extension.as_deref()))?;
+    format.parser(options, siblings).parse(&mut reader)
+}
+
+/// Parses the first dictionary in a tar archive, the way FreeDict and
+/// others publish theirs. Tar archives can only be read in order, so every
+/// file is read first, for dictionaries made of several files to find the
+/// others in the archive.
+fn parse_tar(reader: Box<dyn Read + '_>, options: &ImportOptions) -> Result<Dictionary, ImportError> {
+    let mut archive = tar::Archive::new(reader);
+    let mut files = HashMap::new();
+    // Archives also hold READMEs, licenses and such
+    let mut dictionary_name = None;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
         }
-        ".typo" => {
-            let parser = typo::TypoEQParser;
-            parser.parse(file)
+        let name = entry.path()?.display().to_string();
+        if dictionary_name.is_none() && is_dictionary_file(&name) {
+            dictionary_name = Some(name.clone());
         }
-        _ => {
-            panic!("Parser does not exist for {} files", extension)
-        },
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        files.insert(name, contents);
+    }
+    let name = dictionary_name.ok_or_else(|| ImportError::malformed("there is no dictionary in the archive"))?;
+    let dictionary = files.remove(&name).unwrap_or_default();
+    parse_with_siblings(Box::new(Cursor::new(dictionary)), options, &Siblings::in_archive(&name, files))
+        .map_err(|err| err.with_path(&name))
+}
+
+// Files StarDict and dictd dictionaries are read along with, after their
+// name without the .ifo or .index
+const SIBLING_SUFFIXES: [(&str, &[&str]); 2] = [
+    (".ifo", &["idx", "idx.gz", "dict", "dict.dz"]),
+    (".index", &["dict", "dict.dz"]),
+];
+
+/// The files that may be read along with a dictionary file, for formats
+/// made of several files. Only the ones that exist are given.
+pub fn sibling_paths(file_path: &str) -> Vec<String> {
+    // Compressed files are read as the file they hold
+    let path = Path::new(file_path);
+    let file_path = match path.extension().and_then(|extension| Compression::from_extension(&extension.to_string_lossy())) {
+        Some((_, None)) => path.with_extension("").display().to_string(),
+        _ => file_path.to_string(),
+    };
+    let mut paths: Vec<String> = Vec::new();
+    for (suffix, extensions) in SIBLING_SUFFIXES {
+        let base = file_path.strip_suffix(suffix).unwrap_or(&file_path);
+        for extension in extensions {
+            let sibling = format!("{}.{}", base, extension);
+            if !paths.contains(&sibling) && Path::new(&sibling).is_file() {
+                paths.push(sibling);
+            }
+        }
+    }
+    paths
+}
+
+/// Whether a file is named like a dictionary. Compressed files count when
+/// the file they hold does, and files that go with a dictionary, like the
+/// `.idx` of StarDict, don't count.
+pub fn is_dictionary_file(file_path: &str) -> bool {
+    let path = Path::new(file_path);
+    let Some(extension) = path.extension().map(|extension| extension.to_string_lossy().to_lowercase()) else {
+        return false;
+    };
+    match Compression::from_extension(&extension) {
+        Some((_, Some(_))) => false,
+        Some((_, None)) => is_dictionary_file(&path.with_extension("").display().to_string()),
+        None => extension == "xml" || Format::from_extension(&extension).is_some(),
+    }
+}
+
+/// Every dictionary file in a directory, sorted by name.
+pub fn dictionary_files(dir: &str) -> Result<Vec<String>, ImportError> {
+    let files = fs::read_dir(dir).map_err(|err| ImportError::io(dir, err))?;
+    let mut paths = files.flatten()
+        .filter(|file| file.path().is_file())
+        .map(|file| file.path().display().to_string())
+        .filter(|path| is_dictionary_file(path))
+        .collect::<Vec<String>>();
+    paths.sort();
+    Ok(paths)
+}
+
+/// Reads the first bytes of a file to tell its format, giving back a
+/// reader that still starts at the beginning.
+fn read_header<'a>(mut reader: Box<dyn Read + 'a>) -> Result<(Vec<u8>, Box<dyn Read + 'a>), ImportError> {
+    let mut header = Vec::new();
+    reader.by_ref().take(HEADER_SIZE).read_to_end(&mut header)?;
+    Ok((header.clone(), Box::new(Cursor::new(header).chain(reader))))
+}
+
+/// Finds the format of a file from its first bytes.
+pub fn detect_format(header: &[u8], file_path: &str, options: &ImportOptions) -> Option<Format> {
+    Format::ALL.into_iter()
+        .find(|format| format.parser(options, &Siblings::on_disk(file_path)).can_parse(header))
+}
+
+fn unsupported_format(header: &[u8], extension: Option<&str>) -> ImportError {
+    let format = if let Some(root) = xml_root_name(header) {
+        format!("XML files with a <{}> root element", root)
+    } else if let Some(extension) = extension {
+        format!(".{} files", extension)
+    } else {
+        "files without an extension".to_string()
+    };
+    ImportError::UnsupportedFormat(format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A tar archive of the files given, as FreeDict publishes them
+    fn tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *contents).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn parse_tar_file(archive: Vec<u8>) -> Result<Dictionary, ImportError> {
+        parse_reader(Box::new(Cursor::new(archive)), "dictionary.tar", &ImportOptions::default())
+    }
+
+    fn translations(dict: &Dictionary) -> Vec<(String, Vec<String>)> {
+        dict.words().map(|word| (word.identifier.clone(), word.translation.clone())).collect()
+    }
+
+    #[test]
+    fn dictd_in_tar_reads_its_dict() {
+        let archive = tar(&[
+            ("swe-eng/README", b"Not a dictionary"),
+            ("swe-eng/swe-eng.index", b"hund\tA\tD\nkatt\tD\tD\n"),
+            ("swe-eng/swe-eng.dict", b"dogcat"),
+        ]);
+        let dict = parse_tar_file(archive).unwrap();
+        assert_eq!(translations(&dict), [
+            ("hund".to_string(), vec!["dog".to_string()]),
+            ("katt".to_string(), vec!["cat".to_string()]),
+        ]);
+    }
+
+    #[test]
+    fn stardict_in_tar_reads_its_idx_and_dict() {
+        // Siblings that come before the .ifo are found too
+        let mut index = b"hund\0".to_vec();
+        index.extend(0u32.to_be_bytes());
+        index.extend(3u32.to_be_bytes());
+        let archive = tar(&[
+            ("swe-eng/swe-eng.idx", &index),
+            ("swe-eng/swe-eng.dict", b"dog"),
+            ("swe-eng/swe-eng.ifo", b"StarDict's dict ifo file\nversion=2.4.2\nbookname=swe-eng\nwordcount=1\nsametypesequence=m\n"),
+        ]);
+        let dict = parse_tar_file(archive).unwrap();
+        assert_eq!(translations(&dict), [("hund".to_string(), vec!["dog".to_string()])]);
+        assert_eq!(dict.from(), "swe");
+        assert_eq!(dict.to(), "eng");
+    }
+
+    #[test]
+    fn missing_siblings_in_tar_are_errors() {
+        let archive = tar(&[("swe-eng/swe-eng.index", b"hund\tA\tD\n")]);
+        assert!(matches!(parse_tar_file(archive), Err(ImportError::Io { path, .. }) if path == "swe-eng/swe-eng.dict"));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,234 @@
+use std::{io::{BufReader, BufRead, Read}, collections::HashMap};
+
+use crate::importer::dictionary::{DictionaryWord, DictionaryEntry, Dictionary, DEFAULT_WORD_KIND};
+use crate::importer::error::{ImportError, Position};
+use crate::importer::util::{strip_markup, split_definition, languages_from_title, Siblings};
+
+use super::base::DictionaryParser;
+
+// The line every .ifo file starts with
+const IFO_MAGIC: &str = "StarDict's dict ifo file";
+
+/// Parser for StarDict dictionaries. It is given the `.ifo` file and reads
+/// the `.idx` and `.dict` (or dictzip compressed `.dict.dz`) files that sit
+/// next to it.
+pub struct StarDictParser {
+    pub siblings: Siblings,
+}
+
+impl DictionaryParser for StarDictParser {
+    fn parse(&self, reader: &mut dyn Read) -> Result<Dictionary, ImportError> {
+        // The .ifo file is made of "key=value" lines after a magic line
+        let mut info = HashMap::new();
+        for line in BufReader::new(reader).lines() {
+            let line = line?;
+            if let Some((key, value)) = line.split_once('=') {
+                info.insert(key.trim().to_string(), value.trim().to_string());
+            }
+        }
+        let offset_bits_64 = info.get("idxoffsetbits").map(String::as_str) == Some("64");
+        let type_sequence = info.get("sametypesequence").cloned();
+        let (from, to) = info.get("bookname")
+            .and_then(|bookname| languages_from_title(bookname))
+            .unwrap_or(("Unknown".to_string(), "Unknown".to_string()));
+
+        let index = self.siblings.read(".ifo", &["idx", "idx.gz"])?;
+        let data = self.siblings.read(".ifo", &["dict", "dict.dz"])?;
+
+        let mut entries = Vec::new();
+        let mut position = 0;
+        // Every index entry is a null terminated word followed by the
+        // offset and size of its definition in the .dict file
+        while position < index.len() {
+            let word_end = index[position..].iter()
+                .position(|byte| *byte == 0)
+                .map(|end| position + end)
+                .ok_or_else(|| malformed_index(position, "the word does not end"))?;
+            let identifier = String::from_utf8_lossy(&index[position..word_end]).to_string();
+            position = word_end + 1;
+            let (offset, size) = if offset_bits_64 {
+                (read_u64(&index, position)?, read_u32(&index, position + 8)? as u64)
+            } else {
+                (read_u32(&index, position)? as u64, read_u32(&index, position + 4)? as u64)
+            };
+            position += if offset_bits_64 { 12 } else { 8 };
+            let definition = offset.checked_add(size)
+                .and_then(|end| data.get(usize::try_from(offset).ok()?..usize::try_from(end).ok()?))
+                .ok_or_else(|| ImportError::malformed_at(
+                    Position::Byte(offset),
+                    format!("the definition of {} is past the end of the .dict file", identifier),
+                ))?;
+
+            let mut word = DictionaryWord::new(DEFAULT_WORD_KIND.to_string());
+            word.identifier = identifier;
+            for (kind, field) in parse_fields(definition, type_sequence.as_deref()) {
+                // Only the text fields hold translations, the rest are
+                // sounds, pictures and such
+                if kind == 't' {
+                    word.pronunciation = Some(String::from_utf8_lossy(field).trim().to_string());
+                }
+                if matches!(kind, 'm' | 'l' | 'g' | 'x' | 'h' | 'w') {
+                    let text = String::from_utf8_lossy(field);
+                    let text = if kind == 'm' || kind == 'l' { text.to_string() } else { strip_markup(&text) };
+                    word.translation.extend(split_definition(&text));
+                }
+            }
+            entries.push(DictionaryEntry::Word(word));
+        }
+        Ok(Dictionary::new(entries, from, to))
+    }
+    fn can_parse(&self, header: &[u8]) -> bool {
+        header.starts_with(IFO_MAGIC.as_bytes())
+    }
+}
+
+/// Splits the data of an entry into its typed fields. Lowercase types are
+/// text ending in a null byte and uppercase types are binary data starting
+/// with their size. When the dictionary has a `sametypesequence` the types
+/// are left out of the data, and so is the end of the last field.
+fn parse_fields<'a>(data: &'a [u8], type_sequence: Option<&str>) -> Vec<(char, &'a [u8])> {
+    let mut fields = Vec::new();
+    let mut rest = data;
+    let types = type_sequence.map(|sequence| sequence.chars().collect::<Vec<char>>());
+    let mut index = 0;
+    while !rest.is_empty() {
+        let (kind, is_last) = match types.as_ref() {
+            Some(types) => match types.get(index) {
+                Some(kind) => (*kind, index + 1 == types.len()),
+                None => break,
+            },
+            None => {
+                let kind = rest[0] as char;
+                rest = &rest[1..];
+                (kind, false)
+            }
+        };
+        index += 1;
+        let field;
+        if is_last {
+            field = rest;
+            rest = &[];
+        } else if kind.is_ascii_lowercase() {
+            let end = rest.iter().position(|byte| *byte == 0).unwrap_or(rest.len());
+            field = &rest[..end];
+            rest = rest.get(end + 1..).unwrap_or(&[]);
+        } else {
+            let size = read_u32(rest, 0).unwrap_or(0) as usize;
+            let end = (4 + size).min(rest.len());
+            field = rest.get(4..end).unwrap_or(&[]);
+            rest = &rest[end..];
+        }
+        fields.push((kind, field));
+    }
+    fields
+}
+
+fn read_u32(bytes: &[u8], position: usize) -> Result<u32, ImportError> {
+    bytes.get(position..position + 4)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u32::from_be_bytes)
+        .ok_or_else(|| malformed_index(position, "the index ends early"))
+}
+
+fn read_u64(bytes: &[u8], position: usize) -> Result<u64, ImportError> {
+    bytes.get(position..position + 8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_be_bytes)
+        .ok_or_else(|| malformed_index(position, "the index ends early"))
+}
+
+fn malformed_index(position: usize, message: &str) -> ImportError {
+    ImportError::malformed_at(Position::Byte(position as u64), format!("{} in the .idx file", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const IFO: &str = "StarDict's dict ifo file\nversion=2.4.2\nbookname=swe-eng\nwordcount=2\n";
+
+    // Index entries of 32 bit offsets, or 64 bit ones
+    fn index(entries: &[(&str, u64, u32)], offset_bits_64: bool) -> Vec<u8> {
+        let mut index = Vec::new();
+        for (word, offset, size) in entries {
+            index.extend(word.as_bytes());
+            index.push(0);
+            if offset_bits_64 {
+                index.extend(offset.to_be_bytes());
+            } else {
+                index.extend((*offset as u32).to_be_bytes());
+            }
+            index.extend(size.to_be_bytes());
+        }
+        index
+    }
+
+    fn parse(ifo: &str, index: Vec<u8>, data: &[u8]) -> Result<Dictionary, ImportError> {
+        let files = HashMap::from([
+            ("swe-eng.idx".to_string(), index),
+            ("swe-eng.dict".to_string(), data.to_vec()),
+        ]);
+        let parser = StarDictParser { siblings: Siblings::in_archive("swe-eng.ifo", files) };
+        parser.parse(&mut ifo.as_bytes())
+    }
+
+    fn words(dict: &Dictionary) -> Vec<(String, Vec<String>, Option<String>)> {
+        dict.words()
+            .map(|word| (word.identifier.clone(), word.translation.clone(), word.pronunciation.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn reads_the_words_of_the_idx_from_the_dict() {
+        let data = b"t/h\xca\x89nd/\0mdog; hound\0mcat\0";
+        let index = index(&[("hund", 0, 21), ("katt", 21, 5)], false);
+        let dict = parse(IFO, index, data).unwrap();
+        assert_eq!(words(&dict), [
+            ("hund".to_string(), vec!["dog".to_string(), "hound".to_string()], Some("/hʉnd/".to_string())),
+            ("katt".to_string(), vec!["cat".to_string()], None),
+        ]);
+        assert_eq!(dict.from(), "swe");
+        assert_eq!(dict.to(), "eng");
+    }
+
+    #[test]
+    fn reads_64_bit_offsets() {
+        let ifo = format!("{}idxoffsetbits=64\n", IFO);
+        let index = index(&[("hund", 5, 5), ("katt", 0, 5)], true);
+        let dict = parse(&ifo, index, b"mcat\0mdog\0").unwrap();
+        assert_eq!(words(&dict), [
+            ("hund".to_string(), vec!["dog".to_string()], None),
+            ("katt".to_string(), vec!["cat".to_string()], None),
+        ]);
+    }
+
+    #[test]
+    fn fields_of_a_same_type_sequence_have_no_types() {
+        let ifo = format!("{}sametypesequence=tm\n", IFO);
+        let index = index(&[("hund", 0, 10)], false);
+        let dict = parse(&ifo, index, b"/hund/\0dog").unwrap();
+        assert_eq!(words(&dict), [("hund".to_string(), vec!["dog".to_string()], Some("/hund/".to_string()))]);
+    }
+
+    #[test]
+    fn definitions_past_the_dict_are_malformed() {
+        let index = index(&[("hund", 2, 10)], false);
+        assert!(matches!(parse(IFO, index, b"mdog\0"), Err(ImportError::Malformed { .. })));
+    }
+
+    #[test]
+    fn overflowing_offsets_are_malformed() {
+        let ifo = format!("{}idxoffsetbits=64\n", IFO);
+        let index = index(&[("hund", u64::MAX - 1, 10)], true);
+        assert!(matches!(parse(&ifo, index, b"mdog\0"), Err(ImportError::Malformed { .. })));
+    }
+
+    #[test]
+    fn truncated_indexes_are_malformed() {
+        let mut index = index(&[("hund", 0, 5)], false);
+        index.truncate(7);
+        assert!(matches!(parse(IFO, index, b"mdog\0"), Err(ImportError::Malformed { .. })));
+    }
+}
@@ -0,0 +1,230 @@
+use std::io::{BufReader, Read};
+
+use quick_xml::{Reader, events::{Event, BytesStart}};
+
+use crate::importer::dictionary::{DictionaryWord, DictionaryPhrase, DictionaryEntry, Dictionary, DEFAULT_WORD_KIND};
+use crate::importer::error::{ImportError, Position};
+use crate::importer::util::{languages_from_title, decode_utf8, xml_root_name, word_kind};
+
+use super::base::DictionaryParser;
+
+/// Parser for TEI P5 dictionaries, the format FreeDict distributes its
+/// dictionaries in.
+pub struct TEIParser;
+
+impl DictionaryParser for TEIParser {
+    fn parse(&self, reader: &mut dyn Read) -> Result<Dictionary, ImportError> {
+        let file = BufReader::new(reader);
+        let mut entries = Vec::new();
+        let mut from = None;
+        let mut to = None;
+        let mut title = String::new();
+
+        let mut parser = Reader::from_reader(file);
+        let mut word: Option<DictionaryWord> = None;
+        let mut entry_phrases: Vec<DictionaryPhrase> = Vec::new();
+        let mut buf = Vec::new();
+        // Names of the currently open tags
+        let mut path: Vec<String> = Vec::new();
+        // Types of the currently open <cit> tags, examples can have
+        // translations of their own nested in them
+        let mut cits: Vec<String> = Vec::new();
+        loop {
+            let position = parser.buffer_position();
+            match parser.read_event_into(&mut buf) {
+                Err(e) => return Err(ImportError::malformed_at(Position::Byte(position as u64), e.to_string())),
+                Ok(Event::Eof) => break,
+                Ok(Event::Start(e)) => {
+                    let tag = decode_utf8(e.name().as_ref(), position)?;
+                    match tag.as_str() {
+                        "TEI" | "text" | "body" => {
+                            if from.is_none() {
+                                from = get_attribute(&e, "xml:lang", position)?;
+                            }
+                        }
+                        "entry" => {
+                            word = Some(DictionaryWord::new(DEFAULT_WORD_KIND.to_string()));
+                        }
+                        "cit" => {
+                            let kind = get_attribute(&e, "type", position)?.unwrap_or_default();
+                            if kind == "trans" && cits.is_empty() && to.is_none() {
+                                to = get_attribute(&e, "xml:lang", position)?;
+                            }
+                            if kind == "example" && word.is_some() {
+                                entry_phrases.push(DictionaryPhrase::new(kind.clone()));
+                            }
+                            cits.push(kind);
+                        }
+                        _ => {}
+                    }
+                    path.push(tag);
+                }
+                Ok(Event::Text(e)) => {
+                    let text = e.unescape().map_err(|err| {
+                        ImportError::malformed_at(Position::Byte(position as u64), err.to_string())
+                    })?;
+                    let text = text.trim();
+                    if text.is_empty() {
+                        buf.clear();
+                        continue;
+                    }
+                    let current_tag = path.last().map(String::as_str).unwrap_or_default();
+                    if current_tag == "title" && path.iter().any(|tag| tag == "titleStmt") {
+                        title.push_str(text);
+                    }
+                    if let Some(word) = word.as_mut() {
+                        let cit_types = cits.iter().map(String::as_str).collect::<Vec<&str>>();
+                        match (current_tag, cit_types.as_slice()) {
+                            // The first form is the headword, the others
+                            // are usually inflections of it
+                            ("orth", []) => {
+                                if word.identifier.is_empty() {
+                                    word.identifier = text.to_string();
+                                } else if !word.forms.iter().any(|form| form == text) {
+                                    word.forms.push(text.to_string());
+                                }
+                            }
+                            ("pron", []) => {
+                                if word.pronunciation.is_none() {
+                                    word.pronunciation = Some(text.to_string());
+                                }
+                            }
+                            ("pos", []) => {
+                                word.kind = word_kind(text);
+                            }
+                            ("quote", [.., "example"]) => {
+                                if let Some(phrase) = entry_phrases.last_mut() {
+                                    phrase.identifier.push_str(text);
+                                }
+                            }
+                            ("quote", [.., "example", "trans"]) => {
+                                if let Some(phrase) = entry_phrases.last_mut() {
+                                    if !phrase.translation.is_empty() {
+                                        phrase.translation.push_str("; ");
+                                    }
+                                    phrase.translation.push_str(text);
+                                }
+                            }
+                            ("quote", [.., "trans"]) => {
+                                word.translation.push(text.to_string());
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                Ok(Event::End(e)) => {
+                    let tag = decode_utf8(e.name().as_ref(), position)?;
+                    match tag.as_str() {
+                        "cit" => {
+                            cits.pop();
+                        }
+                        "entry" => {
+                            if let Some(new_word) = word.take() {
+                                entries.push(DictionaryEntry::Word(new_word.clone()));
+                                for mut new_phrase in entry_phrases.drain(..) {
+                                    new_phrase.example_for = new_word.identifier.clone();
+                                    entries.push(DictionaryEntry::Phrase(new_phrase));
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                    path.pop();
+                }
+                _ => {}
+            }
+            buf.clear();
+        }
+        // FreeDict titles look like "German-English FreeDict Dictionary",
+        // which is better than nothing when the languages are not tagged
+        let title_languages = languages_from_title(&title);
+        let (from, to) = match (from, to, title_languages) {
+            (Some(from), Some(to), _) => (from, to),
+            (_, _, Some(title_languages)) => title_languages,
+            (from, to, None) => (
+                from.unwrap_or("Unknown".to_string()),
+                to.unwrap_or("Unknown".to_string()),
+            ),
+        };
+        Ok(Dictionary::new(entries, from, to))
+    }
+    fn can_parse(&self, header: &[u8]) -> bool {
+        // Older dictionaries still use the TEI P4 root element
+        matches!(xml_root_name(header).as_deref(), Some("TEI" | "TEI.2"))
+    }
+}
+
+fn get_attribute(tag: &BytesStart, name: &str, position: usize) -> Result<Option<String>, ImportError> {
+    match tag.try_get_attribute(name) {
+        Ok(Some(attr)) => Ok(Some(decode_utf8(&attr.value, position)?)),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(lang: &str, entries: &str) -> Dictionary {
+        let tei = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<TEI xmlns="http://www.tei-c.org/ns/1.0"{}>
+<teiHeader><fileDesc><titleStmt><title>Swedish-English FreeDict Dictionary</title></titleStmt></fileDesc></teiHeader>
+<text><body>{}</body></text>
+</TEI>"#,
+            lang,
+            entries,
+        );
+        TEIParser.parse(&mut tei.as_bytes()).unwrap()
+    }
+
+    const ENTRY: &str = r#"<entry>
+<form><orth>hund</orth><orth>hundar</orth><pron>hɵnd</pron></form>
+<gramGrp><pos>n</pos></gramGrp>
+<sense>
+<cit type="trans" xml:lang="en"><quote>dog</quote></cit>
+<cit type="trans" xml:lang="en"><quote>hound</quote></cit>
+<cit type="example"><quote>hunden skäller</quote>
+<cit type="trans" xml:lang="en"><quote>the dog barks</quote></cit>
+</cit>
+</sense>
+</entry>"#;
+
+    #[test]
+    fn entries_are_read_with_forms_and_translations() {
+        let dict = parse("", ENTRY);
+        let word = dict.words().next().unwrap();
+        assert_eq!(word.identifier, "hund");
+        assert_eq!(word.forms, ["hundar"]);
+        assert_eq!(word.pronunciation.as_deref(), Some("hɵnd"));
+        assert_eq!(word.kind, "noun");
+        // Translations of examples are not translations of the word
+        assert_eq!(word.translation, ["dog", "hound"]);
+    }
+
+    #[test]
+    fn examples_are_phrases_of_their_entry() {
+        let dict = parse("", ENTRY);
+        let phrases = dict.phrases().collect::<Vec<&DictionaryPhrase>>();
+        assert_eq!(phrases.len(), 1);
+        assert_eq!(phrases[0].kind, "example");
+        assert_eq!(phrases[0].identifier, "hunden skäller");
+        assert_eq!(phrases[0].translation, "the dog barks");
+        assert_eq!(phrases[0].example_for, "hund");
+    }
+
+    #[test]
+    fn languages_are_read_from_tags_before_the_title() {
+        let dict = parse(r#" xml:lang="sv""#, ENTRY);
+        assert_eq!((dict.from(), dict.to()), ("sv", "en"));
+        let dict = parse("", "<entry><form><orth>hund</orth></form><sense><cit type=\"trans\"><quote>dog</quote></cit></sense></entry>");
+        assert_eq!((dict.from(), dict.to()), ("Swedish", "English"));
+    }
+
+    #[test]
+    fn only_tei_roots_can_be_parsed() {
+        assert!(TEIParser.can_parse(br#"<?xml version="1.0"?><TEI xmlns="http://www.tei-c.org/ns/1.0">"#));
+        assert!(TEIParser.can_parse(b"<!DOCTYPE TEI.2><TEI.2>"));
+        assert!(!TEIParser.can_parse(br#"<?xml version="1.0"?><xdxf lang_from="SWE">"#));
+    }
+}
@@ -1,24 +1,311 @@
-use std::{fs::File, io::{SeekFrom, BufReader, Seek}};
+//! The native `.typo` dictionary format.
+//!
+//! It is a line oriented text format meant to be written by hand. Every
+//! line starts with a marker that says what the rest of the line is, and
+//! blank lines or lines starting with `#` are ignored:
+//!
+//! ```text
+//! # Swedish words for the kitchen
+//! @from Swedish
+//! @to English
+//!
+//! + kniv
+//! ~ noun
+//! - knife
+//!
+//! + skära
+//! ~ verb
+//! = skar
+//! % ²ʃæ:ra
+//! - to cut
+//! - to slice
+//! > Hon skär brödet.
+//! < She slices the bread.
+//! ```
+//!
+//! - `@from` and `@to` set the languages of the dictionary
+//! - `+` starts a new word
+//! - `-` adds a translation to the current word
+//! - `=` adds another form of the current word, like an inflection
+//! - `%` sets the pronunciation of the current word
+//! - `>` adds an example phrase for the current word
+//! - `<` sets the translation of the current phrase
+//! - `^` links the current phrase to some other word
+//! - `~` sets the kind of the last word or phrase
+//!
+//! Values can't span lines, so a new line inside of a value is written as
+//! `\n`, a tab as `\t`, a backslash as `\\` and a space at either end of
+//! the value as `\s`. Any other whitespace is written by its code point,
+//! like `\u{a0}` for a no-break space.
 
-use crate::importer::dictionary::{DictionaryWord, DictionaryPhrase, DictionaryEntry, Dictionary};
+use std::io::{BufReader, BufRead, BufWriter, Read, Write};
+
+use crate::importer::dictionary::{DictionaryWord, DictionaryPhrase, DictionaryEntry, Dictionary, DEFAULT_WORD_KIND};
+use crate::importer::error::{ImportError, Position};
 
 use super::base::DictionaryParser;
 
+pub const DEFAULT_PHRASE_KIND: &str = "phrase";
+
 pub struct TypoEQParser;
 
 impl DictionaryParser for TypoEQParser {
-    fn parse(self: &Self, mut file: File) -> crate::importer::dictionary::Dictionary {
-        file.seek(SeekFrom::Start(0)).unwrap();
-        let file = BufReader::new(file);
+    fn parse(&self, reader: &mut dyn Read) -> Result<Dictionary, ImportError> {
+        let file = BufReader::new(reader);
         let mut entries: Vec<DictionaryEntry> = Vec::new();
-        let mut words: Vec<DictionaryWord> = Vec::new();
-        let mut phrases: Vec<DictionaryPhrase> = Vec::new();
-        let mut word: Option<DictionaryWord> = None;
-        let mut phrase: Option<DictionaryPhrase> = None;
-        let mut buf = String::new();
+        // Index of the word translations and phrases belong to
+        let mut word_index: Option<usize> = None;
         let mut from = "Unknown".to_string();
-        let mut to = "Unkown".to_string();
-        todo!();
-        return Dictionary { entries, words, phrases, from, to };
+        let mut to = "Unknown".to_string();
+        for (index, line) in file.lines().enumerate() {
+            let line = line?;
+            let position = Position::Line(index + 1);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (marker, value) = line.split_at(
+                line.find(char::is_whitespace).unwrap_or(line.len())
+            );
+            let value = unescape(value.trim_start());
+            match (marker, entries.last_mut()) {
+                ("@from", _) => from = value,
+                ("@to", _) => to = value,
+                ("+", _) => {
+                    let mut word = DictionaryWord::new(DEFAULT_WORD_KIND.to_string());
+                    word.identifier = value;
+                    word_index = Some(entries.len());
+                    entries.push(DictionaryEntry::Word(word));
+                }
+                ("-" | "=" | "%", _) => {
+                    let Some(DictionaryEntry::Word(word)) = word_index.and_then(|i| entries.get_mut(i)) else {
+                        return Err(ImportError::malformed_at(position, format!("{} without a word", marker)));
+                    };
+                    match marker {
+                        "-" => word.translation.push(value),
+                        "=" => word.forms.push(value),
+                        _ => word.pronunciation = Some(value),
+                    }
+                }
+                (">", _) => {
+                    let mut phrase = DictionaryPhrase::new(DEFAULT_PHRASE_KIND.to_string());
+                    phrase.identifier = value;
+                    if let Some(DictionaryEntry::Word(word)) = word_index.and_then(|i| entries.get(i)) {
+                        phrase.example_for = word.identifier.clone();
+                    }
+                    entries.push(DictionaryEntry::Phrase(phrase));
+                }
+                ("<", Some(DictionaryEntry::Phrase(phrase))) => phrase.translation = value,
+                ("^", Some(DictionaryEntry::Phrase(phrase))) => phrase.example_for = value,
+                ("~", Some(DictionaryEntry::Phrase(phrase))) => phrase.kind = value,
+                ("~", Some(DictionaryEntry::Word(word))) => word.kind = value,
+                ("<" | "^", _) => {
+                    return Err(ImportError::malformed_at(position, format!("{} without a phrase", marker)));
+                }
+                ("~", None) => {
+                    return Err(ImportError::malformed_at(position, "~ without a word or phrase"));
+                }
+                _ => {
+                    return Err(ImportError::malformed_at(position, format!("unknown marker {:?}", marker)));
+                }
+            }
+        }
+        Ok(Dictionary::new(entries, from, to))
+    }
+    fn can_parse(&self, header: &[u8]) -> bool {
+        // The first line that isn't a comment is either a language or a
+        // word
+        String::from_utf8_lossy(header).lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .is_some_and(|line| line.starts_with("@from ") || line.starts_with("@to ") || line.starts_with("+ "))
+    }
+}
+
+pub struct TypoEQWriter;
+
+impl TypoEQWriter {
+    pub fn write(&self, dict: &Dictionary, file: impl Write) -> Result<(), String> {
+        let mut file = BufWriter::new(file);
+        self.write_entries(dict, &mut file)
+            .and_then(|_| file.flush())
+            .map_err(|err| format!("Dictionary could not be written (Err: {:?})", err))
+    }
+    fn write_entries(&self, dict: &Dictionary, file: &mut impl Write) -> std::io::Result<()> {
+        writeln!(file, "@from {}", escape(dict.from()))?;
+        writeln!(file, "@to {}", escape(dict.to()))?;
+        // Phrases are linked to the word above them when read back, so
+        // only write the link when it points somewhere else
+        let mut last_word = String::new();
+        for entry in dict.entries() {
+            match entry {
+                DictionaryEntry::Word(word) => {
+                    writeln!(file)?;
+                    writeln!(file, "+ {}", escape(&word.identifier))?;
+                    if word.kind != DEFAULT_WORD_KIND {
+                        writeln!(file, "~ {}", escape(&word.kind))?;
+                    }
+                    for form in &word.forms {
+                        writeln!(file, "= {}", escape(form))?;
+                    }
+                    if let Some(pronunciation) = &word.pronunciation {
+                        writeln!(file, "% {}", escape(pronunciation))?;
+                    }
+                    for translation in &word.translation {
+                        writeln!(file, "- {}", escape(translation))?;
+                    }
+                    last_word = word.identifier.clone();
+                }
+                DictionaryEntry::Phrase(phrase) => {
+                    writeln!(file, "> {}", escape(&phrase.identifier))?;
+                    if phrase.kind != DEFAULT_PHRASE_KIND {
+                        writeln!(file, "~ {}", escape(&phrase.kind))?;
+                    }
+                    if !phrase.translation.is_empty() {
+                        writeln!(file, "< {}", escape(&phrase.translation))?;
+                    }
+                    if phrase.example_for != last_word {
+                        writeln!(file, "^ {}", escape(&phrase.example_for))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn escape(value: &str) -> String {
+    let chars = value.chars().collect::<Vec<char>>();
+    // Lines are trimmed when read, so whitespace at the edges must be kept
+    // some other way. Spaces inside of the value are left as they are
+    let start = chars.iter().position(|c| !c.is_whitespace()).unwrap_or(chars.len());
+    let end = chars.iter().rposition(|c| !c.is_whitespace()).map_or(start, |end| end + 1);
+    let mut result = String::with_capacity(value.len());
+    for (index, c) in chars.iter().enumerate() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            ' ' if index < start || index >= end => result.push_str("\\s"),
+            ' ' => result.push(' '),
+            c if c.is_whitespace() => result.push_str(&format!("\\u{{{:x}}}", *c as u32)),
+            c => result.push(*c),
+        }
+    }
+    result
+}
+
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('t') => result.push('\t'),
+            Some('s') => result.push(' '),
+            Some('u') => {
+                // Other whitespace is written by its code point, like
+                // \u{a0} for a no-break space
+                let rest = chars.as_str();
+                let code = rest.strip_prefix('{').and_then(|rest| rest.split_once('}'));
+                let escaped = code.and_then(|(hex, _)| u32::from_str_radix(hex, 16).ok())
+                    .and_then(char::from_u32);
+                match (code, escaped) {
+                    (Some((_, after)), Some(c)) => {
+                        result.push(c);
+                        chars = after.chars();
+                    }
+                    _ => result.push('u'),
+                }
+            }
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(dict: &Dictionary) -> Dictionary {
+        let mut written = Vec::new();
+        TypoEQWriter.write(dict, &mut written).unwrap();
+        TypoEQParser.parse(&mut written.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn round_trip_keeps_every_entry() {
+        let mut word = DictionaryWord::new("verb".to_string());
+        word.identifier = "skära".to_string();
+        word.forms = vec!["skar".to_string(), "skurit".to_string()];
+        word.pronunciation = Some("²ʃæ:ra".to_string());
+        word.translation = vec!["to cut".to_string(), "to slice".to_string()];
+        let mut phrase = DictionaryPhrase::new(DEFAULT_PHRASE_KIND.to_string());
+        phrase.identifier = "Hon skär brödet.".to_string();
+        phrase.translation = "She slices the bread.".to_string();
+        phrase.example_for = "skära".to_string();
+        let mut other = DictionaryWord::new(DEFAULT_WORD_KIND.to_string());
+        other.identifier = "kniv".to_string();
+        other.translation = vec!["knife".to_string()];
+        let mut linked = DictionaryPhrase::new("idiom".to_string());
+        linked.identifier = "skära guld med täljkniv".to_string();
+        linked.example_for = "skära".to_string();
+        let dict = Dictionary::new(vec![
+            DictionaryEntry::Word(word),
+            DictionaryEntry::Phrase(phrase),
+            DictionaryEntry::Word(other),
+            DictionaryEntry::Phrase(linked),
+        ], "Swedish".to_string(), "English".to_string());
+        let read = round_trip(&dict);
+        assert_eq!(read.entries(), dict.entries());
+        assert_eq!(read.from(), "Swedish");
+        assert_eq!(read.to(), "English");
+    }
+
+    #[test]
+    fn kinds_are_read_as_written() {
+        let mut word = DictionaryWord::new("Name".to_string());
+        word.identifier = "Stockholm".to_string();
+        let mut other = DictionaryWord::new("n, vb".to_string());
+        other.identifier = "skär".to_string();
+        let dict = Dictionary::new(vec![
+            DictionaryEntry::Word(word),
+            DictionaryEntry::Word(other),
+        ], "Swedish".to_string(), "English".to_string());
+        let read = round_trip(&dict);
+        assert_eq!(read.words().map(|word| word.kind.as_str()).collect::<Vec<&str>>(), ["Name", "n, vb"]);
+    }
+
+    #[test]
+    fn round_trip_keeps_whitespace_and_escapes() {
+        let values = [
+            " leading and trailing ",
+            "\u{a0}no-break spaces\u{a0}",
+            "\ttabs\t",
+            "line\nbreaks\r\n",
+            "back\\slash \\s \\u{41}",
+            "\u{2003}\u{3000} ",
+        ];
+        let entries = values.iter()
+            .map(|value| {
+                let mut word = DictionaryWord::new(DEFAULT_WORD_KIND.to_string());
+                word.identifier = value.to_string();
+                word.translation = vec![value.to_string()];
+                DictionaryEntry::Word(word)
+            })
+            .collect::<Vec<DictionaryEntry>>();
+        let dict = Dictionary::new(entries, " from ".to_string(), "\u{a0}to".to_string());
+        let read = round_trip(&dict);
+        assert_eq!(read.entries(), dict.entries());
+        assert_eq!(read.from(), " from ");
+        assert_eq!(read.to(), "\u{a0}to");
     }
-}
\ No newline at end of file
+}
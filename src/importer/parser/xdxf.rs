@@ -1,108 +1,343 @@
-use std::{fs::File, io::{SeekFrom, BufReader, Seek}};
+use std::io::{BufReader, Read};
 
 use quick_xml::{Reader, events::Event};
 
-use crate::importer::dictionary::{DictionaryWord, DictionaryPhrase, DictionaryEntry, Dictionary};
+use crate::importer::dictionary::{DictionaryWord, DictionaryPhrase, DictionaryEntry, Dictionary, DEFAULT_WORD_KIND};
+use crate::importer::error::{ImportError, Position};
+use crate::importer::util::{split_definition, strip_sense_number, decode_utf8, xml_root_name, word_kind};
 
 use super::base::DictionaryParser;
 
 pub struct XDXFParser;
 
+/// Everything found in an `<ar>` article so far.
+#[derive(Default)]
+struct Article {
+    keys: Vec<String>,
+    transcription: String,
+    grammar: String,
+    translations: Vec<String>,
+    phrases: Vec<DictionaryPhrase>,
+    // Text of the <dtrn> being read
+    dtrn: String,
+    // Text of every open <def>, nested definitions become translations of
+    // their own
+    defs: Vec<String>,
+    // Whether an open <def> had translations in <dtrn> tags, in which case
+    // its text is not a translation itself
+    defs_with_dtrn: Vec<bool>,
+    // Whether an open <def> points to another article with a <kref>, in
+    // which case it is a cross-reference rather than a translation
+    defs_with_kref: Vec<bool>,
+    // Text written straight into the article, as visual format does
+    text: String,
+}
+
+impl XDXFParser {
+    fn finish_article(article: Article) -> Option<(DictionaryWord, Vec<DictionaryPhrase>)> {
+        let mut keys = article.keys.into_iter()
+            .map(|key| collapse_whitespace(&key))
+            .filter(|key| !key.is_empty());
+        let mut word = DictionaryWord::new(DEFAULT_WORD_KIND.to_string());
+        word.identifier = keys.next()?;
+        word.forms = keys.collect();
+        let transcription = collapse_whitespace(&article.transcription);
+        if !transcription.is_empty() {
+            word.pronunciation = Some(transcription);
+        }
+        let grammar = collapse_whitespace(&article.grammar);
+        if !grammar.is_empty() {
+            word.kind = word_kind(&grammar);
+        }
+        word.translation = article.translations;
+        // Visual format articles are only text, with a line per meaning
+        if word.translation.is_empty() {
+            for line in article.text.lines() {
+                let line = collapse_whitespace(line);
+                let line = strip_sense_number(&line).unwrap_or(&line);
+                word.translation.extend(split_definition(line));
+            }
+        }
+        let phrases = article.phrases.into_iter()
+            .filter(|phrase| !phrase.identifier.is_empty())
+            .map(|mut phrase| {
+                phrase.example_for = word.identifier.clone();
+                phrase
+            })
+            .collect();
+        Some((word, phrases))
+    }
+}
+
 impl DictionaryParser for XDXFParser {
-    fn parse(self: &Self, mut file: File) -> crate::importer::dictionary::Dictionary {
-        file.seek(SeekFrom::Start(0)).unwrap();
-        let file = BufReader::new(file);
+    fn parse(&self, reader: &mut dyn Read) -> Result<Dictionary, ImportError> {
+        let file = BufReader::new(reader);
         let mut entries = Vec::new();
-        let mut words = Vec::new();
-        let mut phrases = Vec::new();
         let mut from = "Unkown".to_string();
         let mut to   = "Unkown".to_string();
 
         let mut parser = Reader::from_reader(file);
-        let mut word = None;
-        let mut phrase = None;
+        let mut article: Option<Article> = None;
         let mut buf = Vec::new();
-        let mut current_tag = String::new();
-        let mut current_attrs = Vec::new();
+        // Names of the tags open inside of the current article
+        let mut path: Vec<String> = Vec::new();
         loop {
+            let position = parser.buffer_position();
             match parser.read_event_into(&mut buf) {
-                Err(e) => panic!("Error at position {}: {:?}", parser.buffer_position(), e),
+                Err(e) => return Err(ImportError::malformed_at(Position::Byte(position as u64), e.to_string())),
                 Ok(Event::Eof) => break,
                 Ok(Event::Start(e)) => {
-                    let tag = e.name();
-                    let tag = String::from_utf8(tag.as_ref().to_vec()).unwrap();
-                    if tag.as_str() == "ar" {
-                        let new_entry = DictionaryWord::new(tag.clone());
-                        word = Some(new_entry);
-                    }
+                    let tag = decode_utf8(e.name().as_ref(), position)?;
                     if tag.as_str() == "xdxf" {
                         let lang_from = e.try_get_attribute("lang_from");
                         let lang_to = e.try_get_attribute("lang_to");
-                        if let Ok(lang_from) = lang_from {
-                            if let Some(lang_from) = lang_from {
-                                from = String::from_utf8(lang_from.value.to_vec()).unwrap();
-                            }
+                        if let Ok(Some(lang_from)) = lang_from {
+                            from = decode_utf8(&lang_from.value, position)?;
                         }
-                        if let Ok(lang_to) = lang_to {
-                            if let Some(lang_to) = lang_to {
-                                to = String::from_utf8(lang_to.value.to_vec()).unwrap();
-                            }
+                        if let Ok(Some(lang_to)) = lang_to {
+                            to = decode_utf8(&lang_to.value, position)?;
                         }
                     }
-                    if tag.as_str() == "exm" {
-                        let new_phrase = DictionaryPhrase::new(tag.clone());
-                        phrase = Some(new_phrase);
+                    if tag.as_str() == "ar" {
+                        article = Some(Article::default());
+                        path.clear();
                     }
-                    let attrs = e.attributes().map(|attr| {
-                        if let Ok(attr) = attr {
-                            String::from_utf8(attr.value.to_vec()).unwrap()
-                        } else {
-                            String::new()
+                    if let Some(article) = article.as_mut() {
+                        match tag.as_str() {
+                            "k" => article.keys.push(String::new()),
+                            "def" => {
+                                article.defs.push(String::new());
+                                article.defs_with_dtrn.push(false);
+                                article.defs_with_kref.push(false);
+                            }
+                            "kref" => {
+                                if let Some(has_kref) = article.defs_with_kref.last_mut() {
+                                    *has_kref = true;
+                                }
+                            }
+                            "dtrn" => {
+                                article.dtrn.clear();
+                                for has_dtrn in article.defs_with_dtrn.iter_mut() {
+                                    *has_dtrn = true;
+                                }
+                            }
+                            "ex" => {
+                                let kind = match e.try_get_attribute("type") {
+                                    Ok(Some(attr)) => decode_utf8(&attr.value, position)?,
+                                    _ => tag.clone(),
+                                };
+                                article.phrases.push(DictionaryPhrase::new(kind));
+                            }
+                            _ => {}
                         }
-                    });
-                    current_tag = tag;
-                    current_attrs = attrs.collect();
+                        path.push(tag);
+                    }
+                }
+                Ok(Event::Empty(e)) => {
+                    // Line breaks separate meanings in visual format
+                    if let Some(article) = article.as_mut() {
+                        if e.name().as_ref() == b"br" {
+                            article.text.push('\n');
+                        }
+                    }
                 }
                 Ok(Event::Text(e)) => {
-                    if let Some(entry) = word.as_mut() {
-                        if current_tag == "k" {
-                            entry.identifier = e.unescape().unwrap().to_string();
-                            if let Some(phrase) = phrase.as_mut() {
-                                phrase.example_for = entry.identifier.clone();
+                    if let Some(article) = article.as_mut() {
+                        let text = e.unescape().map_err(|err| {
+                            ImportError::malformed_at(Position::Byte(position as u64), err.to_string())
+                        })?;
+                        let inside = |tag: &str| path.iter().any(|open| open == tag);
+                        if inside("k") {
+                            // Optional parts of a key are left out
+                            if !inside("opt") {
+                                if let Some(key) = article.keys.last_mut() {
+                                    key.push_str(&text);
+                                }
                             }
-                        }
-                        if current_tag == "dtrn" {
-                            entry.translation.push(e.unescape().unwrap().to_string());
-                        }
-                        if current_tag == "ex" && current_attrs.contains(&"exm".to_string()) {
-                            if let Some(phrase) = phrase.as_mut() {
-                                phrase.identifier = e.unescape().unwrap().to_string();
+                        } else if inside("tr") {
+                            article.transcription.push_str(&text);
+                        } else if inside("gr") {
+                            article.grammar.push_str(&text);
+                            article.grammar.push(' ');
+                        } else if inside("ex") {
+                            if let Some(phrase) = article.phrases.last_mut() {
+                                if inside("ex_tran") {
+                                    phrase.translation.push_str(&text);
+                                } else {
+                                    phrase.identifier.push_str(&text);
+                                }
                             }
+                        } else if inside("co") || inside("abr") || inside("kref") {
+                            // Comments and abbreviations are notes about
+                            // usage, and references name other words, none
+                            // of them are meanings
+                        } else if inside("dtrn") {
+                            article.dtrn.push_str(&text);
+                        } else if let Some(def) = article.defs.last_mut() {
+                            def.push_str(&text);
+                        } else {
+                            article.text.push_str(&text);
                         }
                     }
                 }
                 Ok(Event::End(e)) => {
-                    let tag = e.name();
-                    let tag = String::from_utf8(tag.as_ref().to_vec()).unwrap();
+                    let tag = decode_utf8(e.name().as_ref(), position)?;
+                    if let Some(current) = article.as_mut() {
+                        match tag.as_str() {
+                            "dtrn" => {
+                                let dtrn = collapse_whitespace(&current.dtrn);
+                                if !dtrn.is_empty() {
+                                    current.translations.push(dtrn);
+                                }
+                            }
+                            "def" => {
+                                let def = current.defs.pop().unwrap_or_default();
+                                let has_dtrn = current.defs_with_dtrn.pop().unwrap_or(false);
+                                let has_kref = current.defs_with_kref.pop().unwrap_or(false);
+                                let def = collapse_whitespace(&def);
+                                if !has_dtrn && !has_kref && !def.is_empty() {
+                                    current.translations.push(def);
+                                }
+                            }
+                            "ex" => {
+                                if let Some(phrase) = current.phrases.last_mut() {
+                                    phrase.identifier = collapse_whitespace(&phrase.identifier);
+                                    phrase.translation = collapse_whitespace(&phrase.translation);
+                                    // Examples without <ex_tran> may still
+                                    // have their translation after a dash
+                                    if phrase.translation.is_empty() {
+                                        if let Some((original, translation)) = phrase.identifier.clone().split_once(" — ") {
+                                            phrase.identifier = original.trim().to_string();
+                                            phrase.translation = translation.trim().to_string();
+                                        }
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                        path.pop();
+                    }
                     if tag.as_str() == "ar" {
-                        if let Some(new_word) = word {
-                            words.push(new_word.clone());
+                        if let Some((new_word, new_phrases)) = article.take().and_then(Self::finish_article) {
                             entries.push(DictionaryEntry::Word(new_word));
-                            word = None;
-                        }
-                        if let Some(new_phrase) = phrase {
-                            phrases.push(new_phrase.clone());
-                            entries.push(DictionaryEntry::Phrase(new_phrase));
-                            phrase = None;
+                            for new_phrase in new_phrases {
+                                entries.push(DictionaryEntry::Phrase(new_phrase));
+                            }
                         }
                     }
-                    current_tag = String::new();
-                    current_attrs = Vec::new();
                 }
                 _ => {}
             }
             buf.clear();
         }
-        return Dictionary { entries, words, phrases, from, to };
+        Ok(Dictionary::new(entries, from, to))
+    }
+    fn can_parse(&self, header: &[u8]) -> bool {
+        xml_root_name(header).as_deref() == Some("xdxf")
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(articles: &str) -> Dictionary {
+        let xdxf = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<xdxf lang_from="SWE" lang_to="ENG" format="logical" revision="033">
+<lexicon>{}</lexicon>
+</xdxf>"#,
+            articles,
+        );
+        XDXFParser.parse(&mut xdxf.as_bytes()).unwrap()
+    }
+
+    fn first_word(dict: &Dictionary) -> &DictionaryWord {
+        dict.words().next().unwrap()
+    }
+
+    #[test]
+    fn reads_the_languages() {
+        let dict = parse("");
+        assert_eq!(dict.from(), "SWE");
+        assert_eq!(dict.to(), "ENG");
+    }
+
+    #[test]
+    fn later_keys_are_forms() {
+        let dict = parse("<ar><k>skära</k><k>skar</k><k>skurit</k><def><dtrn>to cut</dtrn></def></ar>");
+        let word = first_word(&dict);
+        assert_eq!(word.identifier, "skära");
+        assert_eq!(word.forms, ["skar", "skurit"]);
+        assert_eq!(word.translation, ["to cut"]);
+        assert_eq!(dict.words().len(), 1);
+    }
+
+    #[test]
+    fn grammar_is_the_kind() {
+        let dict = parse("<ar><k>kniv</k><def><gr>n</gr><dtrn>knife</dtrn></def></ar>");
+        assert_eq!(first_word(&dict).kind, "noun");
+    }
+
+    #[test]
+    fn transcription_is_the_pronunciation() {
+        let dict = parse("<ar><k>kniv</k><def><tr>kni:v</tr><dtrn>knife</dtrn></def></ar>");
+        assert_eq!(first_word(&dict).pronunciation.as_deref(), Some("kni:v"));
+    }
+
+    #[test]
+    fn nested_definitions_are_translations() {
+        let dict = parse(
+            "<ar><k>klar</k><def>\
+                <def>clear</def>\
+                <def>ready <co>for something</co></def>\
+                <def><def>done</def><def>finished</def></def>\
+            </def></ar>"
+        );
+        assert_eq!(first_word(&dict).translation, ["clear", "ready", "done", "finished"]);
+    }
+
+    #[test]
+    fn abbreviations_are_left_out() {
+        let dict = parse("<ar><k>grävling</k><def><abr>zool.</abr> badger</def></ar>");
+        assert_eq!(first_word(&dict).translation, ["badger"]);
+    }
+
+    #[test]
+    fn cross_references_are_not_translations() {
+        let dict = parse(
+            "<ar><k>grävling</k>\
+                <def><abr>zool.</abr> badger</def>\
+                <def>see <kref>gräva</kref></def>\
+            </ar>\
+            <ar><k>gravling</k><def>see <kref>grävling</kref></def></ar>"
+        );
+        let words = dict.words().map(|word| word.translation.clone()).collect::<Vec<Vec<String>>>();
+        assert_eq!(words, [vec!["badger".to_string()], Vec::new()]);
+    }
+
+    #[test]
+    fn examples_are_phrases_of_the_word() {
+        let dict = parse(
+            "<ar><k>skära</k><def><dtrn>to cut</dtrn>\
+                <ex><ex_orig>Hon skär brödet.</ex_orig><ex_tran>She slices the bread.</ex_tran></ex>\
+            </def></ar>"
+        );
+        let phrase = dict.phrases().next().unwrap();
+        assert_eq!(phrase.identifier, "Hon skär brödet.");
+        assert_eq!(phrase.translation, "She slices the bread.");
+        assert_eq!(phrase.example_for, "skära");
+    }
+
+    #[test]
+    fn visual_articles_have_a_translation_per_line() {
+        let dict = parse("<ar><k>hund</k><br/>1. dog<br/>2. hound; cur</ar>");
+        let word = first_word(&dict);
+        assert_eq!(word.identifier, "hund");
+        assert_eq!(word.translation, ["dog", "hound", "cur"]);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,292 @@
+use std::{collections::HashMap, fs::File, io::{self, Read}, path::Path, rc::Rc};
+
+use flate2::read::MultiGzDecoder;
+use quick_xml::{Reader, events::Event};
+
+use super::error::{ImportError, Position};
+
+/// Reads text that must be UTF-8, like the names and values of XML tags,
+/// saying where it was found when it is not.
+pub fn decode_utf8(bytes: &[u8], position: usize) -> Result<String, ImportError> {
+    String::from_utf8(bytes.to_vec())
+        .map_err(|err| ImportError::malformed_at(Position::Byte(position as u64), err.to_string()))
+}
+
+/// Turns lightly marked up text (HTML, XDXF or Pango markup) into plain
+/// text. Tags that break lines become new lines, every other tag is dropped
+/// and the common character entities are decoded.
+pub fn strip_markup(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '<' => {
+                let mut tag = String::new();
+                for c in chars.by_ref() {
+                    if c == '>' {
+                        break;
+                    }
+                    tag.push(c);
+                }
+                let name = tag
+                    .trim_start_matches('/')
+                    .split(|c: char| c.is_whitespace() || c == '/')
+                    .next()
+                    .unwrap_or_default()
+                    .to_lowercase();
+                if matches!(name.as_str(), "br" | "p" | "div" | "li" | "tr" | "def") {
+                    result.push('\n');
+                }
+            }
+            '&' => {
+                let mut entity = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c == ';' || entity.len() > 8 {
+                        break;
+                    }
+                    entity.push(c);
+                    chars.next();
+                }
+                match (chars.peek(), decode_entity(&entity)) {
+                    (Some(';'), Some(decoded)) => {
+                        chars.next();
+                        result.push(decoded);
+                    }
+                    // Not an entity after all, keep it as it was written
+                    _ => {
+                        result.push('&');
+                        result.push_str(&entity);
+                    }
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let code = if let Some(hex) = entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                u32::from_str_radix(hex, 16).ok()
+            } else if let Some(decimal) = entity.strip_prefix('#') {
+                decimal.parse::<u32>().ok()
+            } else {
+                None
+            };
+            code.and_then(char::from_u32)
+        }
+    }
+}
+
+/// Splits a plain text definition into separate translations, one per line
+/// or `;` separated part.
+pub fn split_definition(text: &str) -> Vec<String> {
+    text.split(['\n', ';'])
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// The text of a numbered sense like "1. a dog" or "2) a hound", without
+/// its number. Lines that are not numbered senses give `None`.
+pub fn strip_sense_number(line: &str) -> Option<&str> {
+    let digits = line.find(|c: char| !c.is_ascii_digit())?;
+    if digits == 0 {
+        return None;
+    }
+    let rest = line[digits..].strip_prefix(['.', ':', ')'])?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(rest.trim())
+}
+
+// Names dictionaries give to parts of speech, by the name they are kept
+// under. Swedish ones come from Folkets lexikon, the rest are the usual
+// English abbreviations and the tags kaikki.org and FreeDict use. Short
+// names that mean something else in other dictionaries are left out, like
+// "pl" for plural, "pp" for past participle, "pn" for proper noun or "a"
+// for accusative, so their notes are kept as they are written.
+const PARTS_OF_SPEECH: [(&str, &[&str]); 12] = [
+    ("noun", &["n", "nn", "noun", "subst", "substantive", "pm", "name", "propn"]),
+    ("verb", &["v", "vb", "verb", "vi", "vt", "aux"]),
+    ("adjective", &["adj", "jj", "adjective"]),
+    ("adverb", &["ab", "adv", "adverb"]),
+    ("pronoun", &["pron", "pronoun"]),
+    ("preposition", &["prep", "preposition", "postp"]),
+    ("conjunction", &["kn", "sn", "conj", "conjunction"]),
+    ("interjection", &["in", "int", "interj", "intj", "interjection"]),
+    ("numeral", &["rg", "ro", "num", "numeral"]),
+    ("article", &["art", "article", "det", "determiner"]),
+    ("particle", &["part", "particle"]),
+    ("phrase", &["phr", "phrase", "idiom", "proverb"]),
+];
+
+/// The part of speech named by a grammar note like "n", "vb." or
+/// "adj, comp". Only the first word of the note is looked at.
+pub fn part_of_speech(grammar: &str) -> Option<&'static str> {
+    let name = grammar.trim()
+        .split(|c: char| !c.is_alphabetic())
+        .next()?
+        .to_lowercase();
+    PARTS_OF_SPEECH.iter()
+        .find(|(_, names)| names.contains(&name.as_str()))
+        .map(|(part, _)| *part)
+}
+
+/// The kind to give a word with a grammar note, its part of speech when the
+/// note names one and the note itself when it doesn't.
+pub fn word_kind(grammar: &str) -> String {
+    match part_of_speech(grammar) {
+        Some(part) => part.to_string(),
+        None => grammar.trim().to_string(),
+    }
+}
+
+/// Guesses the languages of a dictionary from its title. Titles usually
+/// name them joined by a dash, like "German-English FreeDict Dictionary"
+/// or "quick_eng-swe".
+pub fn languages_from_title(title: &str) -> Option<(String, String)> {
+    let title = title.replace(" - ", "-");
+    title.split_whitespace().find_map(|token| {
+        let (from, to) = token.split_once('-')?;
+        let from = from.rsplit('_').next().unwrap_or_default();
+        let to = to.split('_').next().unwrap_or_default();
+        let is_language = |lang: &str| !lang.is_empty() && lang.chars().all(char::is_alphabetic);
+        if is_language(from) && is_language(to) {
+            Some((from.to_string(), to.to_string()))
+        } else {
+            None
+        }
+    })
+}
+
+/// The files a dictionary is read along with, like the `.idx` and `.dict`
+/// of StarDict, which are named like it with another extension.
+#[derive(Debug, Clone)]
+pub struct Siblings {
+    path: String,
+    /// The other files of the tar archive the dictionary came in, by their
+    /// path inside of it. Without one they are read from next to the
+    /// dictionary on disk.
+    archive: Option<Rc<HashMap<String, Vec<u8>>>>,
+}
+
+impl Siblings {
+    pub fn on_disk(path: &str) -> Self {
+        Self { path: path.to_string(), archive: None }
+    }
+    pub fn in_archive(path: &str, files: HashMap<String, Vec<u8>>) -> Self {
+        Self { path: path.to_string(), archive: Some(Rc::new(files)) }
+    }
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+    /// The same files, for the dictionary at another path, like the file a
+    /// compressed one holds.
+    pub fn for_path(&self, path: &str) -> Self {
+        Self { path: path.to_string(), archive: self.archive.clone() }
+    }
+    /// Reads all of the first file there is named like the dictionary with
+    /// its `suffix` replaced by one of `extensions`. Files ending in `.gz`
+    /// or `.dz` are decompressed.
+    pub fn read(&self, suffix: &str, extensions: &[&str]) -> Result<Vec<u8>, ImportError> {
+        let base = self.path.strip_suffix(suffix).unwrap_or(&self.path);
+        for extension in extensions {
+            let path = format!("{}.{}", base, extension);
+            let file: Box<dyn Read + '_> = match &self.archive {
+                Some(files) => match files.get(&path) {
+                    Some(file) => Box::new(file.as_slice()),
+                    None => continue,
+                },
+                None if Path::new(&path).exists() => {
+                    Box::new(File::open(&path).map_err(|err| ImportError::io(&path, err))?)
+                }
+                None => continue,
+            };
+            let mut file = if is_gzip_extension(extension) {
+                // Dictzip files are regular gzip files with an index of
+                // chunks in the header, which is only needed for seeking
+                Box::new(MultiGzDecoder::new(file))
+            } else {
+                file
+            };
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents).map_err(|err| ImportError::io(&path, err))?;
+            return Ok(contents);
+        }
+        Err(missing_sibling(base, extensions))
+    }
+}
+
+fn is_gzip_extension(extension: &str) -> bool {
+    extension.ends_with(".gz") || extension.ends_with(".dz")
+}
+
+fn missing_sibling(base: &str, extensions: &[&str]) -> ImportError {
+    ImportError::io(
+        &format!("{}.{}", base, extensions[0]),
+        io::Error::new(io::ErrorKind::NotFound, "the file is missing"),
+    )
+}
+
+/// Name of the root element of an XML document, read from its first bytes.
+/// The declaration, comments and doctype before it are skipped.
+pub fn xml_root_name(header: &[u8]) -> Option<String> {
+    let mut reader = Reader::from_reader(header);
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf).ok()? {
+            Event::Start(e) | Event::Empty(e) => {
+                return String::from_utf8(e.name().as_ref().to_vec()).ok();
+            }
+            Event::Eof => return None,
+            Event::Text(e) if !e.iter().all(u8::is_ascii_whitespace) => return None,
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+/// The first line of a text file, without its byte order mark. Files that
+/// don't start with UTF-8 text have none.
+pub fn first_line(header: &[u8]) -> Option<String> {
+    let header = header.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(header);
+    let line = header.split(|byte| *byte == b'\n').next().unwrap_or_default();
+    let line = std::str::from_utf8(line).ok()?;
+    if line.contains('\0') {
+        return None;
+    }
+    Some(line.trim_end_matches('\r').to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_parts_of_speech() {
+        assert_eq!(word_kind("n"), "noun");
+        assert_eq!(word_kind("vb."), "verb");
+        assert_eq!(word_kind("adj, comp"), "adjective");
+        assert_eq!(word_kind("Particle"), "particle");
+    }
+
+    #[test]
+    fn ambiguous_notes_are_kept_as_written() {
+        assert_eq!(part_of_speech("pl"), None);
+        assert_eq!(word_kind(" pl "), "pl");
+        assert_eq!(word_kind("pp"), "pp");
+        assert_eq!(word_kind("pn"), "pn");
+        assert_eq!(word_kind("a"), "a");
+    }
+}
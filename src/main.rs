@@ -1,17 +1,44 @@
-use std::env;
+use std::{env, process};
 use typo_eq::{
     config::{extract_config, Config},
     app,
+    importer::parser::dictd::list_databases,
 };
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    let config: Config;
-    match extract_config(&args) {
-        Ok(imported_config) => config = imported_config,
-        Err(error) => panic!("{}", error),
+    let config: Config = match extract_config(&args) {
+        Ok(imported_config) => imported_config,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    };
+
+    if let Some(dir) = &config.dict_dir {
+        list_dictd_databases(dir);
+        return;
     }
 
     app::create_app(config);
 }
+
+fn list_dictd_databases(dir: &str) {
+    match list_databases(dir) {
+        Ok(databases) if databases.is_empty() => {
+            println!("No dictd databases found in {}", dir);
+        }
+        Ok(databases) => {
+            println!("dictd databases found in {}:", dir);
+            for database in databases {
+                println!("  {:<24} {}", database.name, database.description);
+                println!("  {:<24} --dict {}", "", database.index_path);
+            }
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    }
+}
@@ -1,24 +1,40 @@
-pub fn get_index(vector: &Vec<String>, key: &str) -> i32 {
+pub fn get_index(vector: &[String], key: &str) -> i32 {
     let lookup = vector.iter().position(|v| v == key);
     match lookup {
-        Some(index) => return index as i32,
-        None => return -1,
+        Some(index) => index as i32,
+        None => -1,
     }
 }
 
+pub fn get_value<'a>(vector: &'a [String], key: &str) -> Option<&'a String> {
+    let index = get_index(vector, key);
+    if index < 0 {
+        return None;
+    }
+    vector.get(index as usize + 1)
+}
+
+/// Every value of an option that can be given more than once, under any of
+/// its names.
+pub fn get_values<'a>(vector: &'a [String], keys: &[&str]) -> Vec<&'a String> {
+    vector.windows(2)
+        .filter(|pair| keys.contains(&pair[0].as_str()))
+        .map(|pair| &pair[1])
+        .collect()
+}
+
 pub fn get_index_of_line(txt: &str, index: usize) -> usize {
     let lines_slice = &txt.split("\n").collect::<Vec<&str>>()[0..index];
     let lines = lines_slice
-        .into_iter()
+        .iter()
         .map(|line| line.len())
         .collect::<Vec<usize>>();
     // Count chars of lines before index
-    let result_index = lines
+    lines
         .into_iter()
         .reduce(|accum, line_len| {
             // Don't forget the \n char
-            return accum + line_len + 1;
+            accum + line_len + 1
         })
-        .unwrap();
-    return result_index;
+        .unwrap()
 }